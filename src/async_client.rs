@@ -0,0 +1,503 @@
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use futures_util::{future, SinkExt, StreamExt};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::sync::{mpsc, oneshot};
+use tokio_util::codec::{FramedRead, FramedWrite};
+
+use super::{BatchFailure, ClientError, Command, HeaderType, Item, MemcacheCodec, Reply,
+            ResponseStatus};
+
+type Responder = oneshot::Sender<Result<Vec<Reply>, ClientError>>;
+type Response = oneshot::Receiver<Result<Vec<Reply>, ClientError>>;
+
+struct Request {
+    command: Command,
+    responder: Responder,
+}
+
+struct Pending {
+    quiet: bool,
+    replies: Vec<Reply>,
+    responder: Responder,
+}
+
+// Requests sent and not yet answered, shared by the tasks writing requests
+// and reading responses.
+#[derive(Default)]
+struct InFlight {
+    // Opaques in the order their requests were sent
+    order: VecDeque<u32>,
+    pending: HashMap<u32, Pending>,
+    // Set once the connection has failed, with the reason
+    closed: Option<(io::ErrorKind, String)>,
+}
+
+impl InFlight {
+    fn finish(&mut self, opaque: u32) {
+        if let Some(pending) = self.pending.remove(&opaque) {
+            let _ = pending.responder.send(Ok(pending.replies));
+        }
+    }
+
+    fn answer(&mut self, opaque: u32, reply: Reply) {
+        let position = match self.order.iter().position(|&sent| sent == opaque) {
+            Some(position) => position,
+            // Nothing is waiting for it
+            None => return,
+        };
+        // The server handles requests in order, so quiet ones sent before this
+        // one that haven't been answered by now never will be.
+        let earlier: Vec<u32> = self.order.drain(..position).collect();
+        let mut waiting = 0;
+        for &sent in earlier.iter().rev() {
+            if self.pending[&sent].quiet {
+                self.finish(sent);
+            } else {
+                self.order.push_front(sent);
+                waiting += 1;
+            }
+        }
+        // Stats come as several replies ending with one with an empty key
+        let done = match reply {
+            Reply::Stat { ref key, .. } if key.is_empty() => true,
+            Reply::Stat { .. } => false,
+            _ => true,
+        };
+        let pending = self.pending.get_mut(&opaque).unwrap();
+        match reply {
+            Reply::Stat { ref key, .. } if key.is_empty() => (),
+            reply => pending.replies.push(reply),
+        }
+        if done {
+            // Right behind the earlier requests still waiting, usually none
+            self.order.remove(waiting);
+            self.finish(opaque);
+        }
+    }
+
+    fn fail(&mut self, kind: io::ErrorKind, message: String) {
+        for (_, pending) in self.pending.drain() {
+            let err = io::Error::new(kind, message.clone());
+            let _ = pending.responder.send(Err(ClientError::Io(err)));
+        }
+        self.order.clear();
+        self.closed = Some((kind, message));
+    }
+}
+
+async fn write_requests<W>(mut sink: FramedWrite<W, MemcacheCodec>,
+                           mut requests: mpsc::UnboundedReceiver<Request>,
+                           in_flight: Arc<Mutex<InFlight>>,
+                           done: oneshot::Sender<()>)
+    where W: AsyncWrite + Unpin
+{
+    let mut opaque: u32 = 0;
+    while let Some(request) = requests.recv().await {
+        opaque = opaque.wrapping_add(1);
+        {
+            let mut in_flight = in_flight.lock().unwrap();
+            if let Some((kind, ref message)) = in_flight.closed {
+                let err = io::Error::new(kind, message.clone());
+                let _ = request.responder.send(Err(ClientError::Io(err)));
+                continue;
+            }
+            in_flight.order.push_back(opaque);
+            in_flight.pending.insert(opaque,
+                                     Pending {
+                                         quiet: request.command.opcode().is_quiet(),
+                                         replies: Vec::new(),
+                                         responder: request.responder,
+                                     });
+        }
+        // Only flush once there's nothing more queued up to send
+        let sent = if requests.is_empty() {
+            sink.send((opaque, request.command)).await
+        } else {
+            sink.feed((opaque, request.command)).await
+        };
+        if let Err(err) = sent {
+            in_flight.lock().unwrap().fail(err.kind(), err.to_string());
+        }
+    }
+    // Every client handle is gone, the reader stops once the requests still
+    // in flight have been answered.
+    let _ = done.send(());
+    let _ = SinkExt::<(u32, Command)>::close(&mut sink).await;
+}
+
+async fn read_responses<R>(mut stream: FramedRead<R, MemcacheCodec>,
+                           in_flight: Arc<Mutex<InFlight>>,
+                           mut done: oneshot::Receiver<()>)
+    where R: AsyncRead + Unpin
+{
+    let mut stopping = false;
+    loop {
+        let next = if stopping {
+            stream.next().await
+        } else {
+            tokio::select! {
+                next = stream.next() => next,
+                _ = &mut done => {
+                    stopping = true;
+                    if in_flight.lock().unwrap().pending.is_empty() {
+                        return;
+                    }
+                    continue;
+                }
+            }
+        };
+        let mut in_flight = in_flight.lock().unwrap();
+        let response = match next {
+            Some(Ok(response)) => response,
+            Some(Err(err)) => return in_flight.fail(err.kind(), err.to_string()),
+            None => {
+                return in_flight.fail(io::ErrorKind::UnexpectedEof,
+                                      "connection closed".to_string())
+            }
+        };
+        let opaque = match response.header {
+            HeaderType::Response(ref h) => h.opaque,
+            HeaderType::Request(_) => {
+                return in_flight.fail(io::ErrorKind::InvalidData,
+                                      "server sent a request".to_string())
+            }
+        };
+        match Reply::from_packet(&response.packet()) {
+            Ok(reply) => in_flight.answer(opaque, reply),
+            Err(err) => {
+                return in_flight.fail(io::ErrorKind::InvalidData,
+                                      format!("invalid reply: {:?}", err))
+            }
+        }
+        if stopping && in_flight.pending.is_empty() {
+            return;
+        }
+    }
+}
+
+// An asynchronous client for one connection. Handles are cheap to clone and
+// any number of requests can be in flight at once, each is matched to its
+// response by opaque.
+//
+// Quiet commands that get no response resolve to Ok(None) once a request sent
+// after them is answered, so a batch of them should end with e.g. noop().
+#[derive(Debug,Clone)]
+pub struct AsyncClient {
+    requests: mpsc::UnboundedSender<Request>,
+}
+
+impl AsyncClient {
+    pub async fn connect<A: ToSocketAddrs>(address: A) -> io::Result<AsyncClient> {
+        let stream = TcpStream::connect(address).await?;
+        stream.set_nodelay(true)?;
+        Ok(AsyncClient::new(stream))
+    }
+
+    // Spawns the tasks driving the connection, so it has to be called from
+    // within a tokio runtime.
+    pub fn new<S>(stream: S) -> AsyncClient
+        where S: AsyncRead + AsyncWrite + Send + 'static
+    {
+        let (reader, writer) = tokio::io::split(stream);
+        let (requests, receiver) = mpsc::unbounded_channel();
+        let (done, stopping) = oneshot::channel();
+        let in_flight = Arc::new(Mutex::new(InFlight::default()));
+        tokio::spawn(write_requests(FramedWrite::new(writer, MemcacheCodec::new()),
+                                    receiver,
+                                    Arc::clone(&in_flight),
+                                    done));
+        tokio::spawn(read_responses(FramedRead::new(reader, MemcacheCodec::new()),
+                                    in_flight,
+                                    stopping));
+        AsyncClient { requests: requests }
+    }
+
+    // Queues the command, requests are sent in the order they're queued.
+    fn send(&self, command: Command) -> Result<Response, ClientError> {
+        let (responder, response) = oneshot::channel();
+        let request = Request {
+            command: command,
+            responder: responder,
+        };
+        self.requests.send(request).map_err(|_| closed())?;
+        Ok(response)
+    }
+
+    // Every reply the command got, none for an unanswered quiet command and
+    // several for stats.
+    async fn call(&self, command: Command) -> Result<Vec<Reply>, ClientError> {
+        let response = self.send(command)?;
+        response.await.map_err(|_| closed())?
+    }
+
+    // Sends any command. Errors from the server come back as
+    // ClientError::Status, and None means a quiet command got no response.
+    pub async fn request(&self, command: Command) -> Result<Option<Reply>, ClientError> {
+        match self.call(command).await?.pop() {
+            Some(Reply::Error(status, message)) => Err(ClientError::Status(status, message)),
+            reply => Ok(reply),
+        }
+    }
+
+    pub async fn get(&self, key: &[u8]) -> Result<Option<Item>, ClientError> {
+        let command = Command::Get { key: key.to_vec(), quiet: false, return_key: false };
+        match self.request(command).await {
+            Ok(Some(Reply::Value { flags, cas, value, .. })) => {
+                Ok(Some(Item {
+                    value: value,
+                    flags: flags,
+                    cas: cas,
+                }))
+            }
+            Ok(reply) => Err(unexpected(reply)),
+            Err(ClientError::Status(ResponseStatus::KeyNotFound, _)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    // Fetches many keys with one GetKQ each, ended by a Noop, so only the hits
+    // are answered. Missing keys are left out of the map.
+    pub async fn get_multi<I>(&self, keys: I) -> Result<HashMap<Vec<u8>, Item>, ClientError>
+        where I: IntoIterator,
+              I::Item: AsRef<[u8]>
+    {
+        let mut gets = Vec::new();
+        for key in keys {
+            gets.push(self.send(Command::Get {
+                key: key.as_ref().to_vec(),
+                quiet: true,
+                return_key: true,
+            })?);
+        }
+        let noop = self.send(Command::Noop)?;
+        noop.await.map_err(|_| closed())??;
+        // Every get has been answered or resolved as a miss by now
+        let replies = future::join_all(gets).await;
+        let mut items = HashMap::new();
+        for reply in replies {
+            match reply.map_err(|_| closed())??.pop() {
+                Some(Reply::Value { flags, cas, key, value }) => {
+                    items.insert(key,
+                                 Item {
+                                     value: value,
+                                     flags: flags,
+                                     cas: cas,
+                                 });
+                }
+                Some(Reply::Error(status, message)) => {
+                    return Err(ClientError::Status(status, message))
+                }
+                None => (),
+                reply => return Err(unexpected(reply)),
+            }
+        }
+        Ok(items)
+    }
+
+    // Sends the commands as their quiet variants ended by a Noop and returns
+    // the ones that failed. Commands without a quiet variant are sent as they
+    // are, only their errors are kept.
+    pub async fn batch<I>(&self, commands: I) -> Result<Vec<BatchFailure>, ClientError>
+        where I: IntoIterator<Item = Command>
+    {
+        let mut responses = Vec::new();
+        for command in commands {
+            responses.push(self.send(command.into_quiet())?);
+        }
+        let noop = self.send(Command::Noop)?;
+        noop.await.map_err(|_| closed())??;
+        let mut failures = Vec::new();
+        for (index, replies) in future::join_all(responses).await.into_iter().enumerate() {
+            for reply in replies.map_err(|_| closed())?? {
+                if let Reply::Error(status, message) = reply {
+                    failures.push(BatchFailure {
+                        index: index,
+                        status: status,
+                        message: message,
+                    });
+                }
+            }
+        }
+        Ok(failures)
+    }
+
+    pub async fn set_multi<I, K, V>(&self, items: I, flags: u32, expiration: u32)
+                                    -> Result<Vec<BatchFailure>, ClientError>
+        where I: IntoIterator<Item = (K, V)>,
+              K: AsRef<[u8]>,
+              V: AsRef<[u8]>
+    {
+        self.batch(items.into_iter().map(|(key, value)| {
+                Command::Set {
+                    key: key.as_ref().to_vec(),
+                    value: value.as_ref().to_vec(),
+                    flags: flags,
+                    expiration: expiration,
+                    cas: 0,
+                    quiet: true,
+                }
+            }))
+            .await
+    }
+
+    pub async fn delete_multi<I>(&self, keys: I) -> Result<Vec<BatchFailure>, ClientError>
+        where I: IntoIterator,
+              I::Item: AsRef<[u8]>
+    {
+        self.batch(keys.into_iter().map(|key| {
+                Command::Delete { key: key.as_ref().to_vec(), cas: 0, quiet: true }
+            }))
+            .await
+    }
+
+    pub async fn set(&self, key: &[u8], value: &[u8], flags: u32, expiration: u32)
+                     -> Result<u64, ClientError> {
+        self.store(Command::Set {
+                key: key.to_vec(),
+                value: value.to_vec(),
+                flags: flags,
+                expiration: expiration,
+                cas: 0,
+                quiet: false,
+            })
+            .await
+    }
+
+    pub async fn add(&self, key: &[u8], value: &[u8], flags: u32, expiration: u32)
+                     -> Result<u64, ClientError> {
+        self.store(Command::Add {
+                key: key.to_vec(),
+                value: value.to_vec(),
+                flags: flags,
+                expiration: expiration,
+                quiet: false,
+            })
+            .await
+    }
+
+    pub async fn replace(&self, key: &[u8], value: &[u8], flags: u32, expiration: u32)
+                         -> Result<u64, ClientError> {
+        self.store(Command::Replace {
+                key: key.to_vec(),
+                value: value.to_vec(),
+                flags: flags,
+                expiration: expiration,
+                cas: 0,
+                quiet: false,
+            })
+            .await
+    }
+
+    pub async fn append(&self, key: &[u8], value: &[u8]) -> Result<u64, ClientError> {
+        self.store(Command::Append {
+                key: key.to_vec(),
+                value: value.to_vec(),
+                cas: 0,
+                quiet: false,
+            })
+            .await
+    }
+
+    pub async fn prepend(&self, key: &[u8], value: &[u8]) -> Result<u64, ClientError> {
+        self.store(Command::Prepend {
+                key: key.to_vec(),
+                value: value.to_vec(),
+                cas: 0,
+                quiet: false,
+            })
+            .await
+    }
+
+    pub async fn delete(&self, key: &[u8]) -> Result<(), ClientError> {
+        match self.request(Command::Delete { key: key.to_vec(), cas: 0, quiet: false }).await? {
+            Some(Reply::Deleted { .. }) => Ok(()),
+            reply => Err(unexpected(reply)),
+        }
+    }
+
+    pub async fn incr(&self, key: &[u8], delta: u64, initial: u64, expiration: u32)
+                      -> Result<u64, ClientError> {
+        self.counter(Command::Incr {
+                key: key.to_vec(),
+                delta: delta,
+                initial: initial,
+                expiration: expiration,
+                quiet: false,
+            })
+            .await
+    }
+
+    pub async fn decr(&self, key: &[u8], delta: u64, initial: u64, expiration: u32)
+                      -> Result<u64, ClientError> {
+        self.counter(Command::Decr {
+                key: key.to_vec(),
+                delta: delta,
+                initial: initial,
+                expiration: expiration,
+                quiet: false,
+            })
+            .await
+    }
+
+    pub async fn flush(&self, expiration: Option<u32>) -> Result<(), ClientError> {
+        match self.request(Command::Flush { expiration: expiration, quiet: false }).await? {
+            Some(Reply::Flushed) => Ok(()),
+            reply => Err(unexpected(reply)),
+        }
+    }
+
+    pub async fn version(&self) -> Result<String, ClientError> {
+        match self.request(Command::Version).await? {
+            Some(Reply::Version(version)) => Ok(version),
+            reply => Err(unexpected(reply)),
+        }
+    }
+
+    pub async fn stats(&self, group: &[u8]) -> Result<HashMap<String, String>, ClientError> {
+        let mut stats = HashMap::new();
+        for reply in self.call(Command::Stat { key: group.to_vec() }).await? {
+            match reply {
+                Reply::Stat { key, value } => {
+                    stats.insert(String::from_utf8_lossy(&key).into_owned(),
+                                 String::from_utf8_lossy(&value).into_owned());
+                }
+                Reply::Error(status, message) => return Err(ClientError::Status(status, message)),
+                reply => return Err(unexpected(Some(reply))),
+            }
+        }
+        Ok(stats)
+    }
+
+    pub async fn noop(&self) -> Result<(), ClientError> {
+        match self.request(Command::Noop).await? {
+            Some(Reply::Noop) => Ok(()),
+            reply => Err(unexpected(reply)),
+        }
+    }
+
+    async fn store(&self, command: Command) -> Result<u64, ClientError> {
+        match self.request(command).await? {
+            Some(Reply::Stored { cas }) => Ok(cas),
+            reply => Err(unexpected(reply)),
+        }
+    }
+
+    async fn counter(&self, command: Command) -> Result<u64, ClientError> {
+        match self.request(command).await? {
+            Some(Reply::Counter { value, .. }) => Ok(value),
+            reply => Err(unexpected(reply)),
+        }
+    }
+}
+
+fn closed() -> ClientError {
+    ClientError::Io(io::Error::new(io::ErrorKind::BrokenPipe, "connection closed"))
+}
+
+fn unexpected(reply: Option<Reply>) -> ClientError {
+    ClientError::Protocol(format!("unexpected reply {:?}", reply))
+}
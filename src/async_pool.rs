@@ -0,0 +1,208 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io;
+use std::mem;
+use std::ops::Deref;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use super::pool::checkout_timeout;
+use super::{AsyncClient, ClientError, PoolConfig, PoolStats};
+
+type Connecting = Pin<Box<dyn Future<Output = Result<AsyncClient, ClientError>> + Send>>;
+
+// A bounded pool of async clients for one server, with the same checkouts,
+// health checks and stats as Pool. An AsyncClient can already be shared by
+// many tasks, pooling spreads them over several connections.
+pub struct AsyncPool {
+    config: PoolConfig,
+    connect: Box<dyn Fn() -> Connecting + Send + Sync>,
+    permits: Semaphore,
+    state: Mutex<(VecDeque<(AsyncClient, Instant)>, PoolStats)>,
+}
+
+// Counts a connection taken out of the pool as closed when dropped, unless
+// it's forgotten once checked out, so the stats stay right when get() is
+// cancelled during a health check.
+struct Open<'a>(&'a AsyncPool);
+
+impl<'a> Drop for Open<'a> {
+    fn drop(&mut self) {
+        self.0.count(|stats| stats.open -= 1);
+    }
+}
+
+async fn timeout<T, F>(limit: Option<Duration>, future: F) -> Result<T, ClientError>
+    where F: Future<Output = Result<T, ClientError>>
+{
+    match limit {
+        Some(limit) => {
+            match tokio::time::timeout(limit, future).await {
+                Ok(result) => result,
+                Err(_) => Err(ClientError::Io(io::Error::from(io::ErrorKind::TimedOut))),
+            }
+        }
+        None => future.await,
+    }
+}
+
+impl AsyncPool {
+    pub fn new(address: &str, config: PoolConfig) -> AsyncPool {
+        let address = address.to_string();
+        AsyncPool::with_connector(config, move || {
+            let address = address.clone();
+            async move { Ok(AsyncClient::connect(&address[..]).await?) }
+        })
+    }
+
+    // The pool's connect_timeout is applied to the connector, and io_timeout
+    // to health checks.
+    pub fn with_connector<F, C>(config: PoolConfig, connect: F) -> AsyncPool
+        where F: Fn() -> C + Send + Sync + 'static,
+              C: Future<Output = Result<AsyncClient, ClientError>> + Send + 'static
+    {
+        AsyncPool {
+            config: config,
+            connect: Box::new(move || Box::pin(connect()) as Connecting),
+            permits: Semaphore::new(config.max_size),
+            state: Mutex::new((VecDeque::new(), PoolStats::default())),
+        }
+    }
+
+    pub fn config(&self) -> &PoolConfig {
+        &self.config
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        let state = self.state.lock().unwrap();
+        PoolStats { idle: state.0.len(), ..state.1 }
+    }
+
+    pub async fn get(&self) -> Result<AsyncPooled<'_>, ClientError> {
+        let permit = match self.permits.try_acquire() {
+            Ok(permit) => permit,
+            Err(_) => {
+                self.count(|stats| stats.waits += 1);
+                let acquire = self.permits.acquire();
+                let permit = match self.config.checkout_timeout {
+                    Some(timeout) => {
+                        match tokio::time::timeout(timeout, acquire).await {
+                            Ok(permit) => permit,
+                            Err(_) => {
+                                self.count(|stats| stats.timeouts += 1);
+                                return Err(checkout_timeout());
+                            }
+                        }
+                    }
+                    None => acquire.await,
+                };
+                permit.expect("the pool's semaphore is never closed")
+            }
+        };
+        loop {
+            let idle = self.state.lock().unwrap().0.pop_back();
+            let client = match idle {
+                Some((_, since)) if self.config.expired(since) => {
+                    self.count(|stats| {
+                        stats.expired += 1;
+                        stats.open -= 1;
+                    });
+                    continue;
+                }
+                Some((client, _)) => {
+                    let open = Open(self);
+                    if self.config.health_check &&
+                       timeout(self.config.io_timeout, client.noop()).await.is_err() {
+                        self.count(|stats| stats.failed_health_checks += 1);
+                        continue;
+                    }
+                    mem::forget(open);
+                    client
+                }
+                None => {
+                    match timeout(self.config.connect_timeout, (self.connect)()).await {
+                        Ok(client) => {
+                            self.count(|stats| {
+                                stats.connects += 1;
+                                stats.open += 1;
+                            });
+                            client
+                        }
+                        Err(err) => {
+                            self.count(|stats| stats.failed_connects += 1);
+                            return Err(err);
+                        }
+                    }
+                }
+            };
+            self.count(|stats| stats.checkouts += 1);
+            return Ok(AsyncPooled {
+                pool: self,
+                client: Some(client),
+                broken: false,
+                _permit: permit,
+            });
+        }
+    }
+
+    // Runs f with a pooled connection, dropping the connection rather than
+    // returning it if f fails with a connection error.
+    pub async fn run<T, F, R>(&self, f: F) -> Result<T, ClientError>
+        where F: FnOnce(AsyncClient) -> R,
+              R: Future<Output = Result<T, ClientError>>
+    {
+        let mut client = self.get().await?;
+        let result = f((*client).clone()).await;
+        if let Err(ref err) = result {
+            if err.is_connection_error() {
+                client.mark_broken();
+            }
+        }
+        result
+    }
+
+    fn count<F: FnOnce(&mut PoolStats)>(&self, count: F) {
+        let mut state = self.state.lock().unwrap();
+        count(&mut state.1);
+    }
+}
+
+// A checked out connection, returned to the pool when dropped.
+pub struct AsyncPooled<'a> {
+    pool: &'a AsyncPool,
+    client: Option<AsyncClient>,
+    broken: bool,
+    _permit: SemaphorePermit<'a>,
+}
+
+impl<'a> AsyncPooled<'a> {
+    // Closes the connection instead of returning it, after an error that
+    // left it unusable.
+    pub fn mark_broken(&mut self) {
+        self.broken = true;
+    }
+}
+
+impl<'a> Deref for AsyncPooled<'a> {
+    type Target = AsyncClient;
+
+    fn deref(&self) -> &AsyncClient {
+        self.client.as_ref().unwrap()
+    }
+}
+
+impl<'a> Drop for AsyncPooled<'a> {
+    fn drop(&mut self) {
+        let client = self.client.take().unwrap();
+        let mut state = self.pool.state.lock().unwrap();
+        if self.broken {
+            state.1.broken += 1;
+            state.1.open -= 1;
+        } else {
+            state.0.push_back((client, Instant::now()));
+        }
+    }
+}
@@ -0,0 +1,536 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use super::{Authenticator, SaslError};
+use super::{packet, read_packet, vbucket_id, Command, CommandError, HeaderType, Opcode,
+            ParseError, Reply, ResponseStatus};
+
+// Quiet requests sent per round trip by get_multi() and batch(). Each chunk
+// is written in one go before any response is read, so its responses have to
+// fit in the socket buffers or both ends could block writing.
+const PIPELINE_CHUNK: usize = 256;
+
+#[derive(Debug)]
+pub enum ClientError {
+    Io(io::Error),
+    Parse(ParseError),
+    Command(CommandError),
+    // The server answered with something other than what was asked for
+    Protocol(String),
+    // The server answered with an error status and message
+    Status(ResponseStatus, String),
+    // A cluster has no server to send the request to
+    NoServers,
+}
+
+impl ClientError {
+    pub fn status(&self) -> Option<ResponseStatus> {
+        match self {
+            &ClientError::Status(status, _) => Some(status),
+            _ => None,
+        }
+    }
+
+    // Whether the connection the error came from can't be trusted any more,
+    // because it failed or got out of step with the server. Errors the server
+    // answered with leave the connection usable.
+    pub fn is_connection_error(&self) -> bool {
+        match self {
+            &ClientError::Io(_) |
+            &ClientError::Parse(_) |
+            &ClientError::Protocol(_) => true,
+            &ClientError::Command(_) |
+            &ClientError::Status(..) |
+            &ClientError::NoServers => false,
+        }
+    }
+}
+
+impl From<io::Error> for ClientError {
+    fn from(err: io::Error) -> ClientError {
+        ClientError::Io(err)
+    }
+}
+
+impl From<ParseError> for ClientError {
+    fn from(err: ParseError) -> ClientError {
+        ClientError::Parse(err)
+    }
+}
+
+impl From<CommandError> for ClientError {
+    fn from(err: CommandError) -> ClientError {
+        ClientError::Command(err)
+    }
+}
+
+impl From<SaslError> for ClientError {
+    fn from(err: SaslError) -> ClientError {
+        match err {
+            SaslError::Io(err) => ClientError::Io(err),
+            SaslError::Parse(err) => ClientError::Parse(err),
+            SaslError::Failed(status, message) => ClientError::Status(status, message),
+            err => ClientError::Protocol(err.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &ClientError::Io(ref err) => write!(f, "i/o error: {}", err),
+            &ClientError::Parse(ref err) => write!(f, "parse error: {}", err),
+            &ClientError::Command(ref err) => write!(f, "invalid reply: {:?}", err),
+            &ClientError::Protocol(ref message) => write!(f, "protocol error: {}", message),
+            &ClientError::Status(status, ref message) => {
+                write!(f, "server returned {:?}: {}", status, message)
+            }
+            &ClientError::NoServers => write!(f, "no servers available"),
+        }
+    }
+}
+
+impl Error for ClientError {}
+
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Item {
+    pub value: Vec<u8>,
+    pub flags: u32,
+    pub cas: u64,
+}
+
+// A command of a batch the server reported an error for, index is its
+// position in the batch.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct BatchFailure {
+    pub index: usize,
+    pub status: ResponseStatus,
+    pub message: String,
+}
+
+// A blocking client for one connection. Every call sends a single request
+// and waits for its reply, errors from the server come back as
+// ClientError::Status.
+#[derive(Debug)]
+pub struct Client<S = TcpStream> {
+    stream: S,
+    buf: Vec<u8>,
+    opaque: u32,
+    vbuckets: usize,
+}
+
+impl Client<TcpStream> {
+    pub fn connect<A: ToSocketAddrs>(address: A) -> io::Result<Client<TcpStream>> {
+        let stream = TcpStream::connect(address)?;
+        stream.set_nodelay(true)?;
+        Ok(Client::new(stream))
+    }
+
+    // Gives up on each of the addresses after connect_timeout, and every read
+    // and write after io_timeout, so a server that hangs fails with an i/o
+    // error. None waits forever.
+    pub fn connect_with_timeouts<A: ToSocketAddrs>(address: A,
+                                                   connect_timeout: Option<Duration>,
+                                                   io_timeout: Option<Duration>)
+                                                   -> io::Result<Client<TcpStream>> {
+        let mut last_err = None;
+        for address in address.to_socket_addrs()? {
+            let stream = match connect_timeout {
+                Some(timeout) => TcpStream::connect_timeout(&address, timeout),
+                None => TcpStream::connect(address),
+            };
+            match stream {
+                Ok(stream) => {
+                    stream.set_nodelay(true)?;
+                    stream.set_read_timeout(io_timeout)?;
+                    stream.set_write_timeout(io_timeout)?;
+                    return Ok(Client::new(stream));
+                }
+                Err(err) => last_err = Some(err),
+            }
+        }
+        Err(last_err.unwrap_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "no addresses to connect to")
+        }))
+    }
+}
+
+impl<S: Read + Write> Client<S> {
+    pub fn new(stream: S) -> Client<S> {
+        Client {
+            stream: stream,
+            buf: Vec::new(),
+            opaque: 0,
+            vbuckets: 0,
+        }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    // Requests carry the vBucket of their key out of vbuckets, a power of two.
+    // With none, the default, they are all sent for vBucket 0.
+    pub fn set_vbuckets(&mut self, vbuckets: usize) {
+        assert!(vbuckets.is_power_of_two() || vbuckets == 0,
+                "the number of vBuckets has to be a power of two");
+        self.vbuckets = vbuckets;
+    }
+
+    pub fn authenticate(&mut self, username: &str, password: &str) -> Result<(), ClientError> {
+        Authenticator::new(username, password).authenticate(&mut self.stream)?;
+        Ok(())
+    }
+
+    // None when the key isn't there.
+    pub fn get(&mut self, key: &[u8]) -> Result<Option<Item>, ClientError> {
+        let command = Command::Get { key: key.to_vec(), quiet: false, return_key: false };
+        match self.request(command) {
+            Ok(Reply::Value { flags, cas, value, .. }) => {
+                Ok(Some(Item {
+                    value: value,
+                    flags: flags,
+                    cas: cas,
+                }))
+            }
+            Ok(reply) => Err(unexpected(Opcode::Get, reply)),
+            Err(ClientError::Status(ResponseStatus::KeyNotFound, _)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    // Fetches many keys with one GetKQ each, ended by a Noop, so only the hits
+    // are answered. Missing keys are left out of the map.
+    pub fn get_multi<I>(&mut self, keys: I) -> Result<HashMap<Vec<u8>, Item>, ClientError>
+        where I: IntoIterator,
+              I::Item: AsRef<[u8]>
+    {
+        let mut items = HashMap::new();
+        let gets = keys.into_iter().map(|key| {
+            Command::Get {
+                key: key.as_ref().to_vec(),
+                quiet: true,
+                return_key: true,
+            }
+        });
+        self.pipeline(gets, |_, reply| {
+            match reply {
+                Reply::Value { flags, cas, key, value } => {
+                    items.insert(key,
+                                 Item {
+                                     value: value,
+                                     flags: flags,
+                                     cas: cas,
+                                 });
+                    Ok(())
+                }
+                Reply::Error(status, message) => Err(ClientError::Status(status, message)),
+                reply => Err(unexpected(Opcode::GetKQ, reply)),
+            }
+        })?;
+        Ok(items)
+    }
+
+    // Sends the commands as their quiet variants, in chunks ended by a Noop,
+    // and returns the ones that failed. Commands without a quiet variant are
+    // sent as they are, only their errors are kept.
+    pub fn batch<I>(&mut self, commands: I) -> Result<Vec<BatchFailure>, ClientError>
+        where I: IntoIterator<Item = Command>
+    {
+        let mut failures = Vec::new();
+        self.pipeline(commands.into_iter().map(Command::into_quiet), |index, reply| {
+            if let Reply::Error(status, message) = reply {
+                failures.push(BatchFailure {
+                    index: index,
+                    status: status,
+                    message: message,
+                });
+            }
+            Ok(())
+        })?;
+        Ok(failures)
+    }
+
+    pub fn set_multi<I, K, V>(&mut self, items: I, flags: u32, expiration: u32)
+                              -> Result<Vec<BatchFailure>, ClientError>
+        where I: IntoIterator<Item = (K, V)>,
+              K: AsRef<[u8]>,
+              V: AsRef<[u8]>
+    {
+        self.batch(items.into_iter().map(|(key, value)| {
+            Command::Set {
+                key: key.as_ref().to_vec(),
+                value: value.as_ref().to_vec(),
+                flags: flags,
+                expiration: expiration,
+                cas: 0,
+                quiet: true,
+            }
+        }))
+    }
+
+    pub fn delete_multi<I>(&mut self, keys: I) -> Result<Vec<BatchFailure>, ClientError>
+        where I: IntoIterator,
+              I::Item: AsRef<[u8]>
+    {
+        self.batch(keys.into_iter().map(|key| {
+            Command::Delete { key: key.as_ref().to_vec(), cas: 0, quiet: true }
+        }))
+    }
+
+    // The storage commands return the new CAS value of the item.
+    pub fn set(&mut self, key: &[u8], value: &[u8], flags: u32, expiration: u32)
+               -> Result<u64, ClientError> {
+        self.store(Command::Set {
+            key: key.to_vec(),
+            value: value.to_vec(),
+            flags: flags,
+            expiration: expiration,
+            cas: 0,
+            quiet: false,
+        })
+    }
+
+    pub fn add(&mut self, key: &[u8], value: &[u8], flags: u32, expiration: u32)
+               -> Result<u64, ClientError> {
+        self.store(Command::Add {
+            key: key.to_vec(),
+            value: value.to_vec(),
+            flags: flags,
+            expiration: expiration,
+            quiet: false,
+        })
+    }
+
+    pub fn replace(&mut self, key: &[u8], value: &[u8], flags: u32, expiration: u32)
+                   -> Result<u64, ClientError> {
+        self.store(Command::Replace {
+            key: key.to_vec(),
+            value: value.to_vec(),
+            flags: flags,
+            expiration: expiration,
+            cas: 0,
+            quiet: false,
+        })
+    }
+
+    pub fn append(&mut self, key: &[u8], value: &[u8]) -> Result<u64, ClientError> {
+        self.store(Command::Append {
+            key: key.to_vec(),
+            value: value.to_vec(),
+            cas: 0,
+            quiet: false,
+        })
+    }
+
+    pub fn prepend(&mut self, key: &[u8], value: &[u8]) -> Result<u64, ClientError> {
+        self.store(Command::Prepend {
+            key: key.to_vec(),
+            value: value.to_vec(),
+            cas: 0,
+            quiet: false,
+        })
+    }
+
+    pub fn delete(&mut self, key: &[u8]) -> Result<(), ClientError> {
+        match self.request(Command::Delete { key: key.to_vec(), cas: 0, quiet: false })? {
+            Reply::Deleted { .. } => Ok(()),
+            reply => Err(unexpected(Opcode::Delete, reply)),
+        }
+    }
+
+    // Returns the counter's new value. A missing key is created with the
+    // initial value, unless expiration is 0xffffffff.
+    pub fn incr(&mut self, key: &[u8], delta: u64, initial: u64, expiration: u32)
+                -> Result<u64, ClientError> {
+        self.counter(Command::Incr {
+            key: key.to_vec(),
+            delta: delta,
+            initial: initial,
+            expiration: expiration,
+            quiet: false,
+        })
+    }
+
+    pub fn decr(&mut self, key: &[u8], delta: u64, initial: u64, expiration: u32)
+                -> Result<u64, ClientError> {
+        self.counter(Command::Decr {
+            key: key.to_vec(),
+            delta: delta,
+            initial: initial,
+            expiration: expiration,
+            quiet: false,
+        })
+    }
+
+    pub fn flush(&mut self, expiration: Option<u32>) -> Result<(), ClientError> {
+        match self.request(Command::Flush { expiration: expiration, quiet: false })? {
+            Reply::Flushed => Ok(()),
+            reply => Err(unexpected(Opcode::Flush, reply)),
+        }
+    }
+
+    pub fn version(&mut self) -> Result<String, ClientError> {
+        match self.request(Command::Version)? {
+            Reply::Version(version) => Ok(version),
+            reply => Err(unexpected(Opcode::Version, reply)),
+        }
+    }
+
+    // An empty group returns the general statistics.
+    pub fn stats(&mut self, group: &[u8]) -> Result<HashMap<String, String>, ClientError> {
+        let opaque = self.send(Command::Stat { key: group.to_vec() })?;
+        let mut stats = HashMap::new();
+        loop {
+            match self.receive(Opcode::Stat, opaque)? {
+                Reply::Stat { ref key, .. } if key.is_empty() => return Ok(stats),
+                Reply::Stat { key, value } => {
+                    stats.insert(String::from_utf8_lossy(&key).into_owned(),
+                                 String::from_utf8_lossy(&value).into_owned());
+                }
+                reply => return Err(unexpected(Opcode::Stat, reply)),
+            }
+        }
+    }
+
+    pub fn noop(&mut self) -> Result<(), ClientError> {
+        match self.request(Command::Noop)? {
+            Reply::Noop => Ok(()),
+            reply => Err(unexpected(Opcode::Noop, reply)),
+        }
+    }
+
+    // The server closes the connection once it has answered.
+    pub fn quit(mut self) -> Result<(), ClientError> {
+        match self.request(Command::Quit { quiet: false })? {
+            Reply::Quit => Ok(()),
+            reply => Err(unexpected(Opcode::Quit, reply)),
+        }
+    }
+
+    fn store(&mut self, command: Command) -> Result<u64, ClientError> {
+        let opcode = command.opcode();
+        match self.request(command)? {
+            Reply::Stored { cas } => Ok(cas),
+            reply => Err(unexpected(opcode, reply)),
+        }
+    }
+
+    fn counter(&mut self, command: Command) -> Result<u64, ClientError> {
+        let opcode = command.opcode();
+        match self.request(command)? {
+            Reply::Counter { value, .. } => Ok(value),
+            reply => Err(unexpected(opcode, reply)),
+        }
+    }
+
+    fn request(&mut self, command: Command) -> Result<Reply, ClientError> {
+        let opcode = command.opcode();
+        let opaque = self.send(command)?;
+        self.receive(opcode, opaque)
+    }
+
+    fn send(&mut self, command: Command) -> Result<u32, ClientError> {
+        self.opaque = self.opaque.wrapping_add(1);
+        // One write per request rather than one per packet field
+        let mut request = Vec::new();
+        command.write_to_vbucket(self.opaque, self.vbucket(&command), &mut request)?;
+        self.stream.write_all(&request)?;
+        self.stream.flush()?;
+        Ok(self.opaque)
+    }
+
+    // Writes the commands in chunks, each ended by a Noop, and hands every
+    // reply to f with the position of its command. The replies of a chunk are
+    // read up to its Noop even after an error, so the connection can still be
+    // used, but no further chunks are sent.
+    fn pipeline<I, F>(&mut self, commands: I, mut f: F) -> Result<(), ClientError>
+        where I: IntoIterator<Item = Command>,
+              F: FnMut(usize, Reply) -> Result<(), ClientError>
+    {
+        let mut commands = commands.into_iter().peekable();
+        let mut sent = 0;
+        let mut failure = None;
+        while commands.peek().is_some() && failure.is_none() {
+            let first = self.opaque.wrapping_add(1);
+            let mut requests = Vec::new();
+            for command in commands.by_ref().take(PIPELINE_CHUNK) {
+                self.opaque = self.opaque.wrapping_add(1);
+                command.write_to_vbucket(self.opaque, self.vbucket(&command), &mut requests)?;
+            }
+            let chunk = self.opaque.wrapping_sub(first).wrapping_add(1) as usize;
+            self.opaque = self.opaque.wrapping_add(1);
+            let noop = self.opaque;
+            Command::Noop.write_to(noop, &mut requests)?;
+            self.stream.write_all(&requests)?;
+            self.stream.flush()?;
+            loop {
+                read_packet(&mut self.stream, &mut self.buf)?;
+                let (_, response) = packet(&self.buf)?;
+                let opaque = match response.header {
+                    HeaderType::Response(ref h) => h.opaque,
+                    HeaderType::Request(_) => {
+                        return Err(ClientError::Protocol("server sent a request".to_string()))
+                    }
+                };
+                if opaque == noop {
+                    break;
+                }
+                let offset = opaque.wrapping_sub(first) as usize;
+                let result = if offset < chunk {
+                    Reply::from_packet(&response)
+                        .map_err(ClientError::from)
+                        .and_then(|reply| f(sent + offset, reply))
+                } else {
+                    Err(ClientError::Protocol(format!("unexpected opaque {}", opaque)))
+                };
+                if let Err(err) = result {
+                    failure = failure.or(Some(err));
+                }
+            }
+            sent += chunk;
+        }
+        match failure {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    fn vbucket(&self, command: &Command) -> u16 {
+        if self.vbuckets == 0 || command.key().is_empty() {
+            return 0;
+        }
+        vbucket_id(command.key(), self.vbuckets)
+    }
+
+    fn receive(&mut self, opcode: Opcode, opaque: u32) -> Result<Reply, ClientError> {
+        read_packet(&mut self.stream, &mut self.buf)?;
+        let (_, response) = packet(&self.buf)?;
+        match response.header {
+            HeaderType::Response(ref h) if h.opcode == opcode && h.opaque == opaque => (),
+            ref header => {
+                return Err(ClientError::Protocol(format!("expected a {:?} response with \
+                                                          opaque {}, got {:?}",
+                                                         opcode,
+                                                         opaque,
+                                                         header)))
+            }
+        }
+        match Reply::from_packet(&response)? {
+            Reply::Error(status, message) => Err(ClientError::Status(status, message)),
+            reply => Ok(reply),
+        }
+    }
+}
+
+fn unexpected(opcode: Opcode, reply: Reply) -> ClientError {
+    ClientError::Protocol(format!("unexpected {:?} in response to {:?}", reply, opcode))
+}
@@ -0,0 +1,433 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+use super::{BatchFailure, Client, ClientError, Command, Distributor, Item, Ketama};
+
+// A server of a cluster, its weight is relative to the other nodes'.
+#[derive(Debug,Clone,PartialEq,Eq,Hash)]
+pub struct Node {
+    pub host: String,
+    pub port: u16,
+    pub weight: u32,
+}
+
+impl Node {
+    pub fn new(host: &str, port: u16) -> Node {
+        Node {
+            host: host.to_string(),
+            port: port,
+            weight: 1,
+        }
+    }
+
+    pub fn with_weight(mut self, weight: u32) -> Node {
+        self.weight = weight;
+        self
+    }
+}
+
+// When nodes are taken out of the cluster, like libmemcached's auto eject.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct FailoverConfig {
+    // Connection errors in a row before a node is ejected, never if None or
+    // if the cluster has no connector to replace the failed client
+    pub failure_limit: Option<u32>,
+    // How long after being ejected a node is probed, doubled after every
+    // failed probe up to max_retry_timeout
+    pub retry_timeout: Duration,
+    pub max_retry_timeout: Duration,
+    // Limits on connecting and on each read or write, so a node that hangs
+    // fails like one that's down. Applied by the connector.
+    pub connect_timeout: Option<Duration>,
+    pub io_timeout: Option<Duration>,
+}
+
+impl Default for FailoverConfig {
+    fn default() -> FailoverConfig {
+        FailoverConfig {
+            failure_limit: Some(2),
+            retry_timeout: Duration::from_secs(2),
+            max_retry_timeout: Duration::from_secs(60),
+            connect_timeout: Some(Duration::from_secs(1)),
+            io_timeout: Some(Duration::from_secs(1)),
+        }
+    }
+}
+
+#[derive(Debug,Clone,Copy,Default)]
+struct Health {
+    failures: u32,
+    // The connection failed, and is replaced before the client is used again
+    broken: bool,
+    // When an ejected node is probed next, and the wait before that
+    retry: Option<(Instant, Duration)>,
+}
+
+type Connector<S> =
+    Box<dyn FnMut(&Node, &FailoverConfig) -> Result<Client<S>, ClientError> + Send>;
+
+fn connect(node: &Node, failover: &FailoverConfig) -> Result<Client<TcpStream>, ClientError> {
+    Ok(Client::connect_with_timeouts((&node.host[..], node.port),
+                                     failover.connect_timeout,
+                                     failover.io_timeout)?)
+}
+
+// A blocking client for a pool of servers, each key goes to the node the
+// distributor picks for it, ketama unless another is given. Requests for
+// several keys are split up by node, and commands without a key go to the
+// node of the empty key.
+//
+// Nodes that keep failing with connection errors are ejected, the
+// distributor only sees the live ones. Ejected nodes are probed with a Noop
+// before requests once their retry timeout is up, and put back when it's
+// answered.
+pub struct Cluster<S = TcpStream, D = Ketama> {
+    nodes: Vec<Node>,
+    // None until a node that was down when the cluster was made is connected
+    clients: Vec<Option<Client<S>>>,
+    distributor: D,
+    // Indexes of the nodes that aren't ejected, in the distributor's order
+    live: Vec<usize>,
+    health: Vec<Health>,
+    failover: FailoverConfig,
+    connect: Option<Connector<S>>,
+}
+
+impl<S: fmt::Debug, D: fmt::Debug> fmt::Debug for Cluster<S, D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Cluster")
+            .field("nodes", &self.nodes)
+            .field("clients", &self.clients)
+            .field("distributor", &self.distributor)
+            .field("live", &self.live)
+            .field("health", &self.health)
+            .field("failover", &self.failover)
+            .finish()
+    }
+}
+
+impl Cluster<TcpStream> {
+    pub fn connect(nodes: Vec<Node>) -> Result<Cluster<TcpStream>, ClientError> {
+        let ketama = Ketama::new(&nodes);
+        Cluster::connect_with_distributor(nodes, ketama)
+    }
+}
+
+impl<D: Distributor> Cluster<TcpStream, D> {
+    // Broken connections are replaced by connecting again, with the
+    // failover config's timeouts. Nodes that can't be connected to are
+    // counted as failed, and connected to when they're next used.
+    pub fn connect_with_distributor(nodes: Vec<Node>, distributor: D)
+                                    -> Result<Cluster<TcpStream, D>, ClientError> {
+        let failover = FailoverConfig::default();
+        let (clients, errors): (Vec<_>, Vec<_>) = nodes.iter()
+            .map(|node| match connect(node, &failover) {
+                Ok(client) => (Some(client), None),
+                Err(err) => (None, Some(err)),
+            })
+            .unzip();
+        let mut cluster =
+            Cluster::with_clients(nodes, clients, distributor).with_connector(connect);
+        for (index, err) in errors.into_iter().enumerate() {
+            if let Some(err) = err {
+                cluster.failed(index, err);
+            }
+        }
+        Ok(cluster)
+    }
+}
+
+impl<S: Read + Write> Cluster<S> {
+    // The clients are connected to the nodes at the same positions.
+    pub fn new(nodes: Vec<Node>, clients: Vec<Client<S>>) -> Cluster<S> {
+        let ketama = Ketama::new(&nodes);
+        Cluster::with_distributor(nodes, clients, ketama)
+    }
+}
+
+impl<S: Read + Write, D: Distributor> Cluster<S, D> {
+    // The distributor is updated with the nodes, whatever it was built for.
+    pub fn with_distributor(nodes: Vec<Node>, clients: Vec<Client<S>>, distributor: D)
+                            -> Cluster<S, D> {
+        assert_eq!(nodes.len(), clients.len(), "every node needs a client");
+        Cluster::with_clients(nodes, clients.into_iter().map(Some).collect(), distributor)
+    }
+
+    fn with_clients(nodes: Vec<Node>, clients: Vec<Option<Client<S>>>, mut distributor: D)
+                    -> Cluster<S, D> {
+        distributor.update(&nodes);
+        Cluster {
+            live: (0..nodes.len()).collect(),
+            health: vec![Health::default(); nodes.len()],
+            nodes: nodes,
+            clients: clients,
+            distributor: distributor,
+            failover: FailoverConfig::default(),
+            connect: None,
+        }
+    }
+
+    // Connections made with other timeouts are replaced before they're used
+    // again, when there's a connector.
+    pub fn with_failover(mut self, failover: FailoverConfig) -> Cluster<S, D> {
+        if (failover.connect_timeout, failover.io_timeout) !=
+           (self.failover.connect_timeout, self.failover.io_timeout) {
+            for health in &mut self.health {
+                health.broken = true;
+            }
+        }
+        self.failover = failover;
+        self
+    }
+
+    // How to replace the client of a node after a connection error, which
+    // should apply the failover config's timeouts. Without one the failed
+    // client is used again, and nodes are never ejected.
+    pub fn with_connector<F>(mut self, connect: F) -> Cluster<S, D>
+        where F: FnMut(&Node, &FailoverConfig) -> Result<Client<S>, ClientError> + Send + 'static
+    {
+        self.connect = Some(Box::new(connect));
+        self
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    pub fn ejected(&self) -> Vec<&Node> {
+        self.nodes.iter()
+            .zip(&self.health)
+            .filter(|&(_, health)| health.retry.is_some())
+            .map(|(node, _)| node)
+            .collect()
+    }
+
+    pub fn node(&self, key: &[u8]) -> Option<&Node> {
+        self.index(key).ok().map(|index| &self.nodes[index])
+    }
+
+    // The client of the node the key belongs to.
+    pub fn client(&mut self, key: &[u8]) -> Result<&mut Client<S>, ClientError> {
+        self.probe();
+        let index = self.index(key)?;
+        match self.checkout(index) {
+            Ok(_) => Ok(self.clients[index].as_mut().unwrap()),
+            Err(err) => Err(self.failed(index, err)),
+        }
+    }
+
+    pub fn get(&mut self, key: &[u8]) -> Result<Option<Item>, ClientError> {
+        self.with_node(key, |client| client.get(key))
+    }
+
+    pub fn get_multi<I>(&mut self, keys: I) -> Result<HashMap<Vec<u8>, Item>, ClientError>
+        where I: IntoIterator,
+              I::Item: AsRef<[u8]>
+    {
+        self.probe();
+        let mut groups = vec![Vec::new(); self.nodes.len()];
+        for key in keys {
+            let key = key.as_ref().to_vec();
+            groups[self.index(&key)?].push(key);
+        }
+        let mut items = HashMap::new();
+        for (index, keys) in groups.into_iter().enumerate() {
+            if !keys.is_empty() {
+                items.extend(self.with_index(index, |client| client.get_multi(keys))?);
+            }
+        }
+        Ok(items)
+    }
+
+    // Failures keep the index of their command in the whole batch.
+    pub fn batch<I>(&mut self, commands: I) -> Result<Vec<BatchFailure>, ClientError>
+        where I: IntoIterator<Item = Command>
+    {
+        self.probe();
+        let mut groups = vec![Vec::new(); self.nodes.len()];
+        for (index, command) in commands.into_iter().enumerate() {
+            groups[self.index(command.key())?].push((index, command));
+        }
+        let mut failures = Vec::new();
+        for (node, group) in groups.into_iter().enumerate() {
+            if group.is_empty() {
+                continue;
+            }
+            let (indexes, commands): (Vec<usize>, Vec<Command>) = group.into_iter().unzip();
+            for mut failure in self.with_index(node, |client| client.batch(commands))? {
+                failure.index = indexes[failure.index];
+                failures.push(failure);
+            }
+        }
+        failures.sort_by_key(|failure| failure.index);
+        Ok(failures)
+    }
+
+    pub fn set_multi<I, K, V>(&mut self, items: I, flags: u32, expiration: u32)
+                              -> Result<Vec<BatchFailure>, ClientError>
+        where I: IntoIterator<Item = (K, V)>,
+              K: AsRef<[u8]>,
+              V: AsRef<[u8]>
+    {
+        self.batch(items.into_iter().map(|(key, value)| {
+            Command::Set {
+                key: key.as_ref().to_vec(),
+                value: value.as_ref().to_vec(),
+                flags: flags,
+                expiration: expiration,
+                cas: 0,
+                quiet: true,
+            }
+        }))
+    }
+
+    pub fn delete_multi<I>(&mut self, keys: I) -> Result<Vec<BatchFailure>, ClientError>
+        where I: IntoIterator,
+              I::Item: AsRef<[u8]>
+    {
+        self.batch(keys.into_iter().map(|key| {
+            Command::Delete { key: key.as_ref().to_vec(), cas: 0, quiet: true }
+        }))
+    }
+
+    pub fn set(&mut self, key: &[u8], value: &[u8], flags: u32, expiration: u32)
+               -> Result<u64, ClientError> {
+        self.with_node(key, |client| client.set(key, value, flags, expiration))
+    }
+
+    pub fn add(&mut self, key: &[u8], value: &[u8], flags: u32, expiration: u32)
+               -> Result<u64, ClientError> {
+        self.with_node(key, |client| client.add(key, value, flags, expiration))
+    }
+
+    pub fn replace(&mut self, key: &[u8], value: &[u8], flags: u32, expiration: u32)
+                   -> Result<u64, ClientError> {
+        self.with_node(key, |client| client.replace(key, value, flags, expiration))
+    }
+
+    pub fn append(&mut self, key: &[u8], value: &[u8]) -> Result<u64, ClientError> {
+        self.with_node(key, |client| client.append(key, value))
+    }
+
+    pub fn prepend(&mut self, key: &[u8], value: &[u8]) -> Result<u64, ClientError> {
+        self.with_node(key, |client| client.prepend(key, value))
+    }
+
+    pub fn delete(&mut self, key: &[u8]) -> Result<(), ClientError> {
+        self.with_node(key, |client| client.delete(key))
+    }
+
+    pub fn incr(&mut self, key: &[u8], delta: u64, initial: u64, expiration: u32)
+                -> Result<u64, ClientError> {
+        self.with_node(key, |client| client.incr(key, delta, initial, expiration))
+    }
+
+    pub fn decr(&mut self, key: &[u8], delta: u64, initial: u64, expiration: u32)
+                -> Result<u64, ClientError> {
+        self.with_node(key, |client| client.decr(key, delta, initial, expiration))
+    }
+
+    // Flushes every live node.
+    pub fn flush(&mut self, expiration: Option<u32>) -> Result<(), ClientError> {
+        self.probe();
+        for index in self.live.clone() {
+            self.with_index(index, |client| client.flush(expiration))?;
+        }
+        Ok(())
+    }
+
+    fn index(&self, key: &[u8]) -> Result<usize, ClientError> {
+        match self.distributor.node(key) {
+            Some(index) => Ok(self.live[index]),
+            None => Err(ClientError::NoServers),
+        }
+    }
+
+    fn with_node<T, F>(&mut self, key: &[u8], f: F) -> Result<T, ClientError>
+        where F: FnOnce(&mut Client<S>) -> Result<T, ClientError>
+    {
+        self.probe();
+        let index = self.index(key)?;
+        self.with_index(index, f)
+    }
+
+    // Runs f with the node's client, counting connection errors against the
+    // node and ejecting it when they reach the failure limit.
+    fn with_index<T, F>(&mut self, index: usize, f: F) -> Result<T, ClientError>
+        where F: FnOnce(&mut Client<S>) -> Result<T, ClientError>
+    {
+        match self.checkout(index).and_then(|_| f(self.clients[index].as_mut().unwrap())) {
+            Ok(result) => {
+                self.health[index].failures = 0;
+                Ok(result)
+            }
+            Err(err) => Err(self.failed(index, err)),
+        }
+    }
+
+    // Replaces a broken client, if there's a way to connect. Only clusters
+    // with a connector have nodes without a client, which are always broken.
+    fn checkout(&mut self, index: usize) -> Result<(), ClientError> {
+        if self.health[index].broken {
+            if let Some(ref mut connect) = self.connect {
+                self.clients[index] = Some(connect(&self.nodes[index], &self.failover)?);
+                self.health[index].broken = false;
+            }
+        }
+        Ok(())
+    }
+
+    fn failed(&mut self, index: usize, err: ClientError) -> ClientError {
+        if !err.is_connection_error() {
+            self.health[index].failures = 0;
+            return err;
+        }
+        let health = &mut self.health[index];
+        health.broken = true;
+        health.failures += 1;
+        if health.retry.is_none() && self.connect.is_some() &&
+           self.failover.failure_limit.is_some_and(|limit| health.failures >= limit) {
+            let timeout = self.failover.retry_timeout;
+            health.retry = Some((Instant::now() + timeout, timeout));
+            self.update_live();
+        }
+        err
+    }
+
+    // Puts back the ejected nodes that answer a Noop once their retry
+    // timeout is up, the others wait twice as long for the next probe.
+    fn probe(&mut self) {
+        let now = Instant::now();
+        let mut changed = false;
+        for index in 0..self.nodes.len() {
+            let backoff = match self.health[index].retry {
+                Some((at, backoff)) if at <= now => backoff,
+                _ => continue,
+            };
+            let answered = self.checkout(index)
+                .and_then(|_| self.clients[index].as_mut().unwrap().noop());
+            let health = &mut self.health[index];
+            if answered.is_ok() {
+                *health = Health::default();
+                changed = true;
+            } else {
+                let backoff = (backoff * 2).min(self.failover.max_retry_timeout);
+                health.broken = true;
+                health.retry = Some((Instant::now() + backoff, backoff));
+            }
+        }
+        if changed {
+            self.update_live();
+        }
+    }
+
+    fn update_live(&mut self) {
+        let health = &self.health;
+        self.live = (0..self.nodes.len()).filter(|&index| health[index].retry.is_none()).collect();
+        let live: Vec<Node> = self.live.iter().map(|&index| self.nodes[index].clone()).collect();
+        self.distributor.update(&live);
+    }
+}
@@ -0,0 +1,76 @@
+use std::io;
+
+use bytes::{BufMut, BytesMut};
+use tokio_util::codec;
+
+use super::{packet_with_config, Command, Header, HeaderType, OwnedPacket, Packet, ParseError,
+            ParserConfig, HEADER_LENGTH};
+
+// Frames the binary protocol for Framed and friends. Any packet comes out of
+// the decoder, and commands, packets and owned packets can be sent.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct MemcacheCodec {
+    config: ParserConfig,
+}
+
+impl Default for MemcacheCodec {
+    fn default() -> MemcacheCodec {
+        MemcacheCodec::new()
+    }
+}
+
+impl MemcacheCodec {
+    // Limited to what memcached itself accepts, see ParserConfig::default().
+    pub fn new() -> MemcacheCodec {
+        MemcacheCodec::with_config(ParserConfig::default())
+    }
+
+    pub fn with_config(config: ParserConfig) -> MemcacheCodec {
+        MemcacheCodec { config: config }
+    }
+}
+
+impl codec::Decoder for MemcacheCodec {
+    type Item = OwnedPacket;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<OwnedPacket>> {
+        let header = match packet_with_config(src, &self.config) {
+            Ok((_, packet)) => packet.header,
+            Err(ParseError::Incomplete { needed }) => {
+                src.reserve(needed);
+                return Ok(None);
+            }
+            Err(err) => return Err(io::Error::new(io::ErrorKind::InvalidData, err)),
+        };
+        let frame = src.split_to(HEADER_LENGTH + header.body_length() as usize);
+        Ok(Some(OwnedPacket::from_frame(header, frame.freeze())))
+    }
+}
+
+// Commands are sent with the opaque that goes with them.
+impl codec::Encoder<(u32, Command)> for MemcacheCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, (opaque, command): (u32, Command), dst: &mut BytesMut)
+              -> io::Result<()> {
+        command.write_to(opaque, &mut dst.writer())
+    }
+}
+
+impl<'a> codec::Encoder<Packet<'a, HeaderType>> for MemcacheCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, packet: Packet<'a, HeaderType>, dst: &mut BytesMut) -> io::Result<()> {
+        dst.reserve(HEADER_LENGTH + packet.header.body_length() as usize);
+        packet.write_to(&mut dst.writer())
+    }
+}
+
+impl codec::Encoder<OwnedPacket> for MemcacheCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, packet: OwnedPacket, dst: &mut BytesMut) -> io::Result<()> {
+        self.encode(packet.packet(), dst)
+    }
+}
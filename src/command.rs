@@ -0,0 +1,481 @@
+use std::io::{self, Write};
+
+use super::{Extras, ExtrasLengthError, HeaderType, Opcode, Packet, RequestHeader,
+            ResponseHeader, ResponseStatus};
+
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum Command {
+    // Get, GetQ, GetK and GetKQ
+    Get { key: Vec<u8>, quiet: bool, return_key: bool },
+    Set { key: Vec<u8>, value: Vec<u8>, flags: u32, expiration: u32, cas: u64, quiet: bool },
+    Add { key: Vec<u8>, value: Vec<u8>, flags: u32, expiration: u32, quiet: bool },
+    Replace { key: Vec<u8>, value: Vec<u8>, flags: u32, expiration: u32, cas: u64, quiet: bool },
+    Delete { key: Vec<u8>, cas: u64, quiet: bool },
+    Incr { key: Vec<u8>, delta: u64, initial: u64, expiration: u32, quiet: bool },
+    Decr { key: Vec<u8>, delta: u64, initial: u64, expiration: u32, quiet: bool },
+    Quit { quiet: bool },
+    Flush { expiration: Option<u32>, quiet: bool },
+    Noop,
+    Version,
+    Append { key: Vec<u8>, value: Vec<u8>, cas: u64, quiet: bool },
+    Prepend { key: Vec<u8>, value: Vec<u8>, cas: u64, quiet: bool },
+    // An empty key requests all statistics
+    Stat { key: Vec<u8> },
+    Verbosity { level: u32 },
+    Touch { key: Vec<u8>, expiration: u32 },
+    GetAndTouch { key: Vec<u8>, expiration: u32, quiet: bool },
+    SaslListMechanisms,
+    SaslAuth { mechanism: Vec<u8>, data: Vec<u8> },
+    SaslStep { mechanism: Vec<u8>, data: Vec<u8> },
+}
+
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum Reply {
+    Value { flags: u32, cas: u64, key: Vec<u8>, value: Vec<u8> },
+    Stored { cas: u64 },
+    Deleted { cas: u64 },
+    Touched { cas: u64 },
+    Counter { value: u64, cas: u64 },
+    Quit,
+    Flushed,
+    Noop,
+    Version(String),
+    Verbosity,
+    // Stat replies are terminated by one with an empty key
+    Stat { key: Vec<u8>, value: Vec<u8> },
+    // Space separated list of the SASL mechanisms a server supports
+    Mechanisms(String),
+    Authenticated(Vec<u8>),
+    // An AuthenticationContinue status, the body is the mechanism's challenge
+    // and not a message, so it's kept as bytes
+    Challenge(Vec<u8>),
+    Error(ResponseStatus, String),
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum CommandError {
+    NotARequest,
+    NotAResponse,
+    Extras(ExtrasLengthError),
+    InvalidBody { opcode: Opcode, expected: usize, actual: usize },
+    UnsupportedOpcode(Opcode),
+}
+
+impl From<ExtrasLengthError> for CommandError {
+    fn from(err: ExtrasLengthError) -> CommandError {
+        CommandError::Extras(err)
+    }
+}
+
+fn storage_extras(extras: Extras) -> (u32, u32) {
+    match extras {
+        Extras::Storage { flags, expiration } => (flags, expiration),
+        _ => (0, 0),
+    }
+}
+
+fn touch_extras(extras: Extras) -> u32 {
+    match extras {
+        Extras::Touch { expiration } => expiration,
+        _ => 0,
+    }
+}
+
+fn counter_extras(extras: Extras) -> (u64, u64, u32) {
+    match extras {
+        Extras::Counter { delta, initial, expiration } => (delta, initial, expiration),
+        _ => (0, 0, 0),
+    }
+}
+
+impl Command {
+    pub fn from_packet(packet: &Packet<HeaderType>) -> Result<Command, CommandError> {
+        let header = match &packet.header {
+            &HeaderType::Request(ref h) => h,
+            &HeaderType::Response(_) => return Err(CommandError::NotARequest),
+        };
+        let extras = packet.typed_extras()?;
+        let key = packet.key.to_vec();
+        let value = packet.body.to_vec();
+        let cas = header.cas;
+        let command = match header.opcode {
+            Opcode::Get => Command::Get { key: key, quiet: false, return_key: false },
+            Opcode::GetQ => Command::Get { key: key, quiet: true, return_key: false },
+            Opcode::GetK => Command::Get { key: key, quiet: false, return_key: true },
+            Opcode::GetKQ => Command::Get { key: key, quiet: true, return_key: true },
+            Opcode::Set | Opcode::SetQ => {
+                let (flags, expiration) = storage_extras(extras);
+                Command::Set {
+                    key: key,
+                    value: value,
+                    flags: flags,
+                    expiration: expiration,
+                    cas: cas,
+                    quiet: header.opcode == Opcode::SetQ,
+                }
+            }
+            Opcode::Add | Opcode::AddQ => {
+                let (flags, expiration) = storage_extras(extras);
+                Command::Add {
+                    key: key,
+                    value: value,
+                    flags: flags,
+                    expiration: expiration,
+                    quiet: header.opcode == Opcode::AddQ,
+                }
+            }
+            Opcode::Replace | Opcode::ReplaceQ => {
+                let (flags, expiration) = storage_extras(extras);
+                Command::Replace {
+                    key: key,
+                    value: value,
+                    flags: flags,
+                    expiration: expiration,
+                    cas: cas,
+                    quiet: header.opcode == Opcode::ReplaceQ,
+                }
+            }
+            Opcode::Delete | Opcode::DeleteQ => {
+                Command::Delete { key: key, cas: cas, quiet: header.opcode == Opcode::DeleteQ }
+            }
+            Opcode::Increment | Opcode::IncrementQ => {
+                let (delta, initial, expiration) = counter_extras(extras);
+                Command::Incr {
+                    key: key,
+                    delta: delta,
+                    initial: initial,
+                    expiration: expiration,
+                    quiet: header.opcode == Opcode::IncrementQ,
+                }
+            }
+            Opcode::Decrement | Opcode::DecrementQ => {
+                let (delta, initial, expiration) = counter_extras(extras);
+                Command::Decr {
+                    key: key,
+                    delta: delta,
+                    initial: initial,
+                    expiration: expiration,
+                    quiet: header.opcode == Opcode::DecrementQ,
+                }
+            }
+            Opcode::Quit => Command::Quit { quiet: false },
+            Opcode::QuitQ => Command::Quit { quiet: true },
+            Opcode::Flush | Opcode::FlushQ => {
+                let expiration = match extras {
+                    Extras::Flush { expiration } => expiration,
+                    _ => None,
+                };
+                Command::Flush { expiration: expiration, quiet: header.opcode == Opcode::FlushQ }
+            }
+            Opcode::Noop => Command::Noop,
+            Opcode::Version => Command::Version,
+            Opcode::Append | Opcode::AppendQ => {
+                Command::Append {
+                    key: key,
+                    value: value,
+                    cas: cas,
+                    quiet: header.opcode == Opcode::AppendQ,
+                }
+            }
+            Opcode::Prepend | Opcode::PrependQ => {
+                Command::Prepend {
+                    key: key,
+                    value: value,
+                    cas: cas,
+                    quiet: header.opcode == Opcode::PrependQ,
+                }
+            }
+            Opcode::Stat => Command::Stat { key: key },
+            Opcode::Verbosity => {
+                let level = match extras {
+                    Extras::Verbosity { level } => level,
+                    _ => 0,
+                };
+                Command::Verbosity { level: level }
+            }
+            Opcode::Touch => Command::Touch { key: key, expiration: touch_extras(extras) },
+            Opcode::GetAndTouch | Opcode::GetAndTouchQ => {
+                Command::GetAndTouch {
+                    key: key,
+                    expiration: touch_extras(extras),
+                    quiet: header.opcode == Opcode::GetAndTouchQ,
+                }
+            }
+            Opcode::SaslListMechanisms => Command::SaslListMechanisms,
+            Opcode::SaslAuth => Command::SaslAuth { mechanism: key, data: value },
+            Opcode::SaslStep => Command::SaslStep { mechanism: key, data: value },
+            Opcode::Other(_) => return Err(CommandError::UnsupportedOpcode(header.opcode)),
+        };
+        Ok(command)
+    }
+
+    pub fn opcode(&self) -> Opcode {
+        match self {
+            &Command::Get { quiet: false, return_key: false, .. } => Opcode::Get,
+            &Command::Get { quiet: true, return_key: false, .. } => Opcode::GetQ,
+            &Command::Get { quiet: false, return_key: true, .. } => Opcode::GetK,
+            &Command::Get { quiet: true, return_key: true, .. } => Opcode::GetKQ,
+            &Command::Set { quiet, .. } => if quiet { Opcode::SetQ } else { Opcode::Set },
+            &Command::Add { quiet, .. } => if quiet { Opcode::AddQ } else { Opcode::Add },
+            &Command::Replace { quiet, .. } => {
+                if quiet { Opcode::ReplaceQ } else { Opcode::Replace }
+            }
+            &Command::Delete { quiet, .. } => if quiet { Opcode::DeleteQ } else { Opcode::Delete },
+            &Command::Incr { quiet, .. } => {
+                if quiet { Opcode::IncrementQ } else { Opcode::Increment }
+            }
+            &Command::Decr { quiet, .. } => {
+                if quiet { Opcode::DecrementQ } else { Opcode::Decrement }
+            }
+            &Command::Quit { quiet } => if quiet { Opcode::QuitQ } else { Opcode::Quit },
+            &Command::Flush { quiet, .. } => if quiet { Opcode::FlushQ } else { Opcode::Flush },
+            &Command::Noop => Opcode::Noop,
+            &Command::Version => Opcode::Version,
+            &Command::Append { quiet, .. } => if quiet { Opcode::AppendQ } else { Opcode::Append },
+            &Command::Prepend { quiet, .. } => {
+                if quiet { Opcode::PrependQ } else { Opcode::Prepend }
+            }
+            &Command::Stat { .. } => Opcode::Stat,
+            &Command::Verbosity { .. } => Opcode::Verbosity,
+            &Command::Touch { .. } => Opcode::Touch,
+            &Command::GetAndTouch { quiet, .. } => {
+                if quiet { Opcode::GetAndTouchQ } else { Opcode::GetAndTouch }
+            }
+            &Command::SaslListMechanisms => Opcode::SaslListMechanisms,
+            &Command::SaslAuth { .. } => Opcode::SaslAuth,
+            &Command::SaslStep { .. } => Opcode::SaslStep,
+        }
+    }
+
+    // The same command as its quiet variant, if it has one.
+    pub fn into_quiet(mut self) -> Command {
+        match self {
+            Command::Get { ref mut quiet, .. } |
+            Command::Set { ref mut quiet, .. } |
+            Command::Add { ref mut quiet, .. } |
+            Command::Replace { ref mut quiet, .. } |
+            Command::Delete { ref mut quiet, .. } |
+            Command::Incr { ref mut quiet, .. } |
+            Command::Decr { ref mut quiet, .. } |
+            Command::Quit { ref mut quiet } |
+            Command::Flush { ref mut quiet, .. } |
+            Command::Append { ref mut quiet, .. } |
+            Command::Prepend { ref mut quiet, .. } |
+            Command::GetAndTouch { ref mut quiet, .. } => *quiet = true,
+            _ => (),
+        }
+        self
+    }
+
+    pub fn key(&self) -> &[u8] {
+        match self {
+            &Command::Get { ref key, .. } |
+            &Command::Set { ref key, .. } |
+            &Command::Add { ref key, .. } |
+            &Command::Replace { ref key, .. } |
+            &Command::Delete { ref key, .. } |
+            &Command::Incr { ref key, .. } |
+            &Command::Decr { ref key, .. } |
+            &Command::Append { ref key, .. } |
+            &Command::Prepend { ref key, .. } |
+            &Command::Stat { ref key } |
+            &Command::Touch { ref key, .. } |
+            &Command::GetAndTouch { ref key, .. } |
+            &Command::SaslAuth { mechanism: ref key, .. } |
+            &Command::SaslStep { mechanism: ref key, .. } => key,
+            _ => b"",
+        }
+    }
+
+    fn value(&self) -> &[u8] {
+        match self {
+            &Command::Set { ref value, .. } |
+            &Command::Add { ref value, .. } |
+            &Command::Replace { ref value, .. } |
+            &Command::Append { ref value, .. } |
+            &Command::Prepend { ref value, .. } |
+            &Command::SaslAuth { data: ref value, .. } |
+            &Command::SaslStep { data: ref value, .. } => value,
+            _ => b"",
+        }
+    }
+
+    fn cas(&self) -> u64 {
+        match self {
+            &Command::Set { cas, .. } |
+            &Command::Replace { cas, .. } |
+            &Command::Delete { cas, .. } |
+            &Command::Append { cas, .. } |
+            &Command::Prepend { cas, .. } => cas,
+            _ => 0,
+        }
+    }
+
+    pub fn extras(&self) -> Extras {
+        match self {
+            &Command::Set { flags, expiration, .. } |
+            &Command::Add { flags, expiration, .. } |
+            &Command::Replace { flags, expiration, .. } => {
+                Extras::Storage { flags: flags, expiration: expiration }
+            }
+            &Command::Incr { delta, initial, expiration, .. } |
+            &Command::Decr { delta, initial, expiration, .. } => {
+                Extras::Counter { delta: delta, initial: initial, expiration: expiration }
+            }
+            &Command::Flush { expiration, .. } => Extras::Flush { expiration: expiration },
+            &Command::Touch { expiration, .. } |
+            &Command::GetAndTouch { expiration, .. } => Extras::Touch { expiration: expiration },
+            &Command::Verbosity { level } => Extras::Verbosity { level: level },
+            _ => Extras::None,
+        }
+    }
+
+    pub fn write_to<W: Write>(&self, opaque: u32, writer: &mut W) -> io::Result<()> {
+        self.write_to_vbucket(opaque, 0, writer)
+    }
+
+    pub fn write_to_vbucket<W: Write>(&self, opaque: u32, vbucket: u16, writer: &mut W)
+                                      -> io::Result<()> {
+        let mut header = RequestHeader::new(self.opcode());
+        header.opaque = opaque;
+        header.vbucket_id = vbucket;
+        header.cas = self.cas();
+        let extras = self.extras().to_bytes();
+        Packet::new(HeaderType::Request(header), &extras, self.key(), self.value())
+            .write_to(writer)
+    }
+
+    pub fn encode(&self, opaque: u32) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.write_to(opaque, &mut buf)?;
+        Ok(buf)
+    }
+}
+
+fn body_length(opcode: Opcode, body: &[u8], expected: usize) -> Result<(), CommandError> {
+    if body.len() != expected {
+        return Err(CommandError::InvalidBody {
+            opcode: opcode,
+            expected: expected,
+            actual: body.len(),
+        });
+    }
+    Ok(())
+}
+
+impl Reply {
+    pub fn from_packet(packet: &Packet<HeaderType>) -> Result<Reply, CommandError> {
+        let header = match &packet.header {
+            &HeaderType::Response(ref h) => h,
+            &HeaderType::Request(_) => return Err(CommandError::NotAResponse),
+        };
+        let sasl = header.opcode == Opcode::SaslAuth || header.opcode == Opcode::SaslStep;
+        if sasl && header.status == ResponseStatus::AuthenticationContinue {
+            return Ok(Reply::Challenge(packet.body.to_vec()));
+        }
+        if header.status != ResponseStatus::NoError {
+            let message = String::from_utf8_lossy(packet.body).into_owned();
+            return Ok(Reply::Error(header.status, message));
+        }
+        let extras = packet.typed_extras()?;
+        let reply = match header.opcode {
+            Opcode::Get | Opcode::GetQ | Opcode::GetK | Opcode::GetKQ | Opcode::GetAndTouch |
+            Opcode::GetAndTouchQ => {
+                let flags = match extras {
+                    Extras::Value { flags } => flags,
+                    _ => 0,
+                };
+                Reply::Value {
+                    flags: flags,
+                    cas: header.cas,
+                    key: packet.key.to_vec(),
+                    value: packet.body.to_vec(),
+                }
+            }
+            Opcode::Set | Opcode::SetQ | Opcode::Add | Opcode::AddQ | Opcode::Replace |
+            Opcode::ReplaceQ | Opcode::Append | Opcode::AppendQ | Opcode::Prepend |
+            Opcode::PrependQ => Reply::Stored { cas: header.cas },
+            Opcode::Delete | Opcode::DeleteQ => Reply::Deleted { cas: header.cas },
+            Opcode::Touch => Reply::Touched { cas: header.cas },
+            Opcode::Verbosity => Reply::Verbosity,
+            Opcode::Increment | Opcode::IncrementQ | Opcode::Decrement | Opcode::DecrementQ => {
+                body_length(header.opcode, packet.body, 8)?;
+                let mut counter = [0u8; 8];
+                counter.copy_from_slice(packet.body);
+                Reply::Counter {
+                    value: u64::from_be_bytes(counter),
+                    cas: header.cas,
+                }
+            }
+            Opcode::Quit | Opcode::QuitQ => Reply::Quit,
+            Opcode::Flush | Opcode::FlushQ => Reply::Flushed,
+            Opcode::Noop => Reply::Noop,
+            Opcode::Version => Reply::Version(String::from_utf8_lossy(packet.body).into_owned()),
+            Opcode::Stat => {
+                Reply::Stat {
+                    key: packet.key.to_vec(),
+                    value: packet.body.to_vec(),
+                }
+            }
+            Opcode::SaslListMechanisms => {
+                Reply::Mechanisms(String::from_utf8_lossy(packet.body).into_owned())
+            }
+            Opcode::SaslAuth | Opcode::SaslStep => Reply::Authenticated(packet.body.to_vec()),
+            Opcode::Other(_) => return Err(CommandError::UnsupportedOpcode(header.opcode)),
+        };
+        Ok(reply)
+    }
+
+    pub fn status(&self) -> ResponseStatus {
+        match self {
+            &Reply::Error(status, _) => status,
+            &Reply::Challenge(_) => ResponseStatus::AuthenticationContinue,
+            _ => ResponseStatus::NoError,
+        }
+    }
+
+    // Replies don't know which command they answer, so the opcode is needed to
+    // decide between e.g. Get and GetK framing.
+    pub fn write_to<W: Write>(&self, opcode: Opcode, opaque: u32, writer: &mut W)
+                              -> io::Result<()> {
+        let mut header = ResponseHeader::new(opcode, self.status());
+        header.opaque = opaque;
+        let mut extras = Extras::None;
+        let counter = match self {
+            &Reply::Counter { value, .. } => value.to_be_bytes(),
+            _ => [0u8; 8],
+        };
+        let (key, body): (&[u8], &[u8]) = match self {
+            &Reply::Value { flags, cas, ref key, ref value } => {
+                header.cas = cas;
+                extras = Extras::Value { flags: flags };
+                match opcode {
+                    Opcode::GetK | Opcode::GetKQ => (key, value),
+                    _ => (b"", value),
+                }
+            }
+            &Reply::Stored { cas } | &Reply::Touched { cas } | &Reply::Deleted { cas } => {
+                header.cas = cas;
+                (b"", b"")
+            }
+            &Reply::Counter { cas, .. } => {
+                header.cas = cas;
+                (b"", &counter)
+            }
+            &Reply::Version(ref version) => (b"", version.as_bytes()),
+            &Reply::Stat { ref key, ref value } => (key, value),
+            &Reply::Mechanisms(ref mechanisms) => (b"", mechanisms.as_bytes()),
+            &Reply::Authenticated(ref data) | &Reply::Challenge(ref data) => (b"", data),
+            &Reply::Error(_, ref message) => (b"", message.as_bytes()),
+            &Reply::Quit | &Reply::Flushed | &Reply::Noop | &Reply::Verbosity => (b"", b""),
+        };
+        let extras = extras.to_bytes();
+        Packet::new(HeaderType::Response(header), &extras, key, body).write_to(writer)
+    }
+
+    pub fn encode(&self, opcode: Opcode, opaque: u32) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.write_to(opcode, opaque, &mut buf)?;
+        Ok(buf)
+    }
+}
@@ -0,0 +1,72 @@
+use super::{Header, HeaderType, ParseError};
+
+// memcached's own limits: 250 byte keys and 1 MiB items
+pub const DEFAULT_MAX_KEY_LENGTH: u16 = 250;
+pub const DEFAULT_MAX_EXTRAS_LENGTH: u8 = 20;
+pub const DEFAULT_MAX_VALUE_LENGTH: u32 = 1024 * 1024;
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum LengthField {
+    Key,
+    Extras,
+    Body,
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct ParserConfig {
+    pub max_key_length: u16,
+    pub max_extras_length: u8,
+    // Total body length: extras, key and value together
+    pub max_body_length: u32,
+    // Keep unknown opcodes as Opcode::Other instead of failing
+    pub allow_unknown_opcodes: bool,
+}
+
+impl Default for ParserConfig {
+    fn default() -> ParserConfig {
+        ParserConfig {
+            max_key_length: DEFAULT_MAX_KEY_LENGTH,
+            max_extras_length: DEFAULT_MAX_EXTRAS_LENGTH,
+            max_body_length: DEFAULT_MAX_VALUE_LENGTH + DEFAULT_MAX_KEY_LENGTH as u32 +
+                             DEFAULT_MAX_EXTRAS_LENGTH as u32,
+            allow_unknown_opcodes: false,
+        }
+    }
+}
+
+impl ParserConfig {
+    // Anything the header fields can express, what packet() uses.
+    pub fn unlimited() -> ParserConfig {
+        ParserConfig {
+            max_key_length: u16::MAX,
+            max_extras_length: u8::MAX,
+            max_body_length: u32::MAX,
+            allow_unknown_opcodes: false,
+        }
+    }
+
+    pub(crate) fn check(&self, header: &HeaderType) -> Result<(), ParseError> {
+        if header.key_length() > self.max_key_length {
+            return Err(ParseError::TooLarge {
+                field: LengthField::Key,
+                length: header.key_length() as u32,
+                limit: self.max_key_length as u32,
+            });
+        }
+        if header.extras_length() > self.max_extras_length {
+            return Err(ParseError::TooLarge {
+                field: LengthField::Extras,
+                length: header.extras_length() as u32,
+                limit: self.max_extras_length as u32,
+            });
+        }
+        if header.body_length() > self.max_body_length {
+            return Err(ParseError::TooLarge {
+                field: LengthField::Body,
+                length: header.body_length(),
+                limit: self.max_body_length,
+            });
+        }
+        Ok(())
+    }
+}
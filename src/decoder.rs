@@ -0,0 +1,96 @@
+use super::{header, packet_with_config, Header, HeaderType, Packet, ParseError, ParserConfig,
+            HEADER_LENGTH};
+
+// Reassembles packets from whatever chunks a stream hands out. Bytes go in
+// with feed() and complete packets come out of decode(), borrowed from the
+// decoder's buffer until the next call.
+#[derive(Debug,Clone)]
+pub struct Decoder {
+    config: ParserConfig,
+    buf: Vec<u8>,
+    // Bytes at the front of buf belonging to packets already handed out,
+    // dropped on the next feed() rather than after every packet
+    consumed: usize,
+    header: Option<HeaderType>,
+}
+
+impl Default for Decoder {
+    fn default() -> Decoder {
+        Decoder::new()
+    }
+}
+
+impl Decoder {
+    // With memcached's limits, so a header can't make it wait for more than a
+    // large item's worth of body. with_config(ParserConfig::unlimited())
+    // accepts whatever packet() does.
+    pub fn new() -> Decoder {
+        Decoder::with_config(ParserConfig::default())
+    }
+
+    pub fn with_config(config: ParserConfig) -> Decoder {
+        Decoder {
+            config: config,
+            buf: Vec::new(),
+            consumed: 0,
+            header: None,
+        }
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) {
+        if self.consumed > 0 {
+            self.buf.drain(..self.consumed);
+            self.consumed = 0;
+        }
+        self.buf.extend_from_slice(bytes);
+    }
+
+    // The header of the packet being assembled, once its 24 bytes have been
+    // through decode().
+    pub fn header(&self) -> Option<&HeaderType> {
+        self.header.as_ref()
+    }
+
+    // Bytes buffered but not yet returned as part of a packet.
+    pub fn buffered(&self) -> usize {
+        self.buf.len() - self.consumed
+    }
+
+    // How many more bytes the next packet needs. Only a lower bound until
+    // decode() has seen the header, exact from then on.
+    pub fn needed(&self) -> usize {
+        let total = match self.header {
+            Some(ref header) => HEADER_LENGTH + header.body_length() as usize,
+            None => HEADER_LENGTH,
+        };
+        total.saturating_sub(self.buffered())
+    }
+
+    // Returns the next complete packet, or None until more bytes are fed. An
+    // error leaves the stream unrecoverable, as there is no way to find where
+    // the next packet starts.
+    pub fn decode<'a>(&'a mut self) -> Result<Option<Packet<'a, HeaderType>>, ParseError> {
+        let start = self.consumed;
+        if self.header.is_none() {
+            let available = &self.buf[start..];
+            let partial = &available[..available.len().min(HEADER_LENGTH)];
+            match packet_with_config(partial, &self.config) {
+                Ok(_) | Err(ParseError::Incomplete { .. }) => (),
+                Err(err) => return Err(err),
+            }
+            if partial.len() < HEADER_LENGTH {
+                return Ok(None);
+            }
+            // Validated above, so this can't fail
+            self.header = Some(header(partial).unwrap().1);
+        }
+        let total = HEADER_LENGTH + self.header.as_ref().unwrap().body_length() as usize;
+        if self.buffered() < total {
+            return Ok(None);
+        }
+        self.header = None;
+        self.consumed += total;
+        packet_with_config(&self.buf[start..start + total], &self.config)
+            .map(|(_, packet)| Some(packet))
+    }
+}
@@ -0,0 +1,131 @@
+use super::{KeyHash, Node};
+
+// Picks the node a key belongs to. Distributors are built for a list of
+// nodes and answer with indexes into it.
+pub trait Distributor {
+    // None when there are no nodes to pick from.
+    fn node(&self, key: &[u8]) -> Option<usize>;
+
+    // Starts over with another list of nodes, keeping any other settings.
+    fn update(&mut self, nodes: &[Node]);
+}
+
+// libmemcached's modula distribution, the key's hash modulo the number of
+// nodes. Weights are ignored, and any change to the nodes moves most keys.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Modula {
+    hash: KeyHash,
+    nodes: usize,
+}
+
+impl Modula {
+    pub fn new(nodes: &[Node], hash: KeyHash) -> Modula {
+        Modula {
+            hash: hash,
+            nodes: nodes.len(),
+        }
+    }
+}
+
+impl Distributor for Modula {
+    fn node(&self, key: &[u8]) -> Option<usize> {
+        if self.nodes == 0 {
+            return None;
+        }
+        Some(self.hash.hash(key) as usize % self.nodes)
+    }
+
+    fn update(&mut self, nodes: &[Node]) {
+        self.nodes = nodes.len();
+    }
+}
+
+// Rendezvous or highest random weight hashing, each key goes to the node
+// scoring highest for it. Scores hash the node's "host:port" followed by the
+// key, and are scaled by weight so nodes get keys in proportion to it.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Rendezvous {
+    hash: KeyHash,
+    nodes: Vec<(Vec<u8>, f64)>,
+}
+
+impl Rendezvous {
+    pub fn new(nodes: &[Node], hash: KeyHash) -> Rendezvous {
+        let mut rendezvous = Rendezvous {
+            hash: hash,
+            nodes: Vec::new(),
+        };
+        rendezvous.update(nodes);
+        rendezvous
+    }
+}
+
+impl Distributor for Rendezvous {
+    fn node(&self, key: &[u8]) -> Option<usize> {
+        let mut best = None;
+        let mut best_score = 0.0;
+        let mut name = Vec::new();
+        for (index, &(ref node, weight)) in self.nodes.iter().enumerate() {
+            name.clear();
+            name.extend_from_slice(node);
+            name.extend_from_slice(key);
+            // The hash mapped to (0, 1), weighted as -w / ln(h)
+            let point = (self.hash.hash(&name) as f64 + 1.0) / (u32::MAX as f64 + 2.0);
+            let score = -weight / point.ln();
+            if best.is_none() || score > best_score {
+                best = Some(index);
+                best_score = score;
+            }
+        }
+        best
+    }
+
+    fn update(&mut self, nodes: &[Node]) {
+        self.nodes = nodes.iter()
+            .map(|node| (format!("{}:{}", node.host, node.port).into_bytes(), node.weight as f64))
+            .collect();
+    }
+}
+
+// Lamping and Veach's jump consistent hash of the key's hash. It needs no
+// memory per node, but nodes can only be added or removed at the end of the
+// list without moving other keys, and weights are ignored.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct JumpHash {
+    hash: KeyHash,
+    nodes: usize,
+}
+
+impl JumpHash {
+    pub fn new(nodes: &[Node], hash: KeyHash) -> JumpHash {
+        JumpHash {
+            hash: hash,
+            nodes: nodes.len(),
+        }
+    }
+
+    // The bucket out of buckets the key goes to, as in the paper.
+    pub fn bucket(mut key: u64, buckets: usize) -> usize {
+        let mut bucket = 0;
+        let mut next = 0;
+        while next < buckets as i64 {
+            bucket = next;
+            key = key.wrapping_mul(2862933555777941757).wrapping_add(1);
+            next = ((bucket + 1) as f64 * ((1u64 << 31) as f64 / ((key >> 33) + 1) as f64)) as i64;
+        }
+        bucket as usize
+    }
+}
+
+impl Distributor for JumpHash {
+    fn node(&self, key: &[u8]) -> Option<usize> {
+        if self.nodes == 0 {
+            return None;
+        }
+        Some(JumpHash::bucket(self.hash.hash(key) as u64, self.nodes))
+    }
+
+    fn update(&mut self, nodes: &[Node]) {
+        self.nodes = nodes.len();
+    }
+}
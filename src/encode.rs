@@ -0,0 +1,106 @@
+use std::io::{self, Write};
+
+use super::{Header, HeaderType, Packet, RequestHeader, ResponseHeader, HEADER_LENGTH,
+            REQUEST_MAGIC, RESPONSE_MAGIC};
+
+fn header_bytes(magic: u8, opcode: u8, key_length: u16, extras_length: u8,
+                vbucket_or_status: u16, body_length: u32, opaque: u32, cas: u64)
+                -> [u8; HEADER_LENGTH] {
+    let mut buf = [0u8; HEADER_LENGTH];
+    buf[0] = magic;
+    buf[1] = opcode;
+    buf[2..4].copy_from_slice(&key_length.to_be_bytes());
+    buf[4] = extras_length;
+    buf[5] = 0x00; // DataType::Raw
+    buf[6..8].copy_from_slice(&vbucket_or_status.to_be_bytes());
+    buf[8..12].copy_from_slice(&body_length.to_be_bytes());
+    buf[12..16].copy_from_slice(&opaque.to_be_bytes());
+    buf[16..24].copy_from_slice(&cas.to_be_bytes());
+    buf
+}
+
+impl RequestHeader {
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&header_bytes(REQUEST_MAGIC,
+                                       u8::from(self.opcode),
+                                       self.key_length,
+                                       self.extras_length,
+                                       self.vbucket_id,
+                                       self.body_length,
+                                       self.opaque,
+                                       self.cas))
+    }
+}
+
+impl ResponseHeader {
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&header_bytes(RESPONSE_MAGIC,
+                                       u8::from(self.opcode),
+                                       self.key_length,
+                                       self.extras_length,
+                                       u16::from(self.status),
+                                       self.body_length,
+                                       self.opaque,
+                                       self.cas))
+    }
+}
+
+impl HeaderType {
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        match self {
+            &HeaderType::Request(ref r) => r.write_to(writer),
+            &HeaderType::Response(ref r) => r.write_to(writer),
+        }
+    }
+
+    fn set_lengths(&mut self, key_length: u16, extras_length: u8, body_length: u32) {
+        match self {
+            &mut HeaderType::Request(ref mut r) => {
+                r.key_length = key_length;
+                r.extras_length = extras_length;
+                r.body_length = body_length;
+            }
+            &mut HeaderType::Response(ref mut r) => {
+                r.key_length = key_length;
+                r.extras_length = extras_length;
+                r.body_length = body_length;
+            }
+        }
+    }
+}
+
+impl<'a> Packet<'a, HeaderType> {
+    // Builds a packet whose header lengths describe the given slices. Slices too
+    // long for their header field are caught when the packet is written.
+    pub fn new(mut header: HeaderType, extras: &'a [u8], key: &'a [u8], body: &'a [u8])
+               -> Packet<'a, HeaderType> {
+        let total = extras.len() + key.len() + body.len();
+        header.set_lengths(key.len() as u16, extras.len() as u8, total as u32);
+        Packet {
+            header: header,
+            extras: extras,
+            key: key,
+            body: body,
+        }
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let total = self.extras.len() + self.key.len() + self.body.len();
+        if self.header.extras_length() as usize != self.extras.len() ||
+           self.header.key_length() as usize != self.key.len() ||
+           self.header.body_length() as usize != total {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "packet header lengths do not match its contents"));
+        }
+        self.header.write_to(writer)?;
+        writer.write_all(self.extras)?;
+        writer.write_all(self.key)?;
+        writer.write_all(self.body)
+    }
+
+    pub fn encode(&self) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(HEADER_LENGTH + self.header.body_length() as usize);
+        self.write_to(&mut buf)?;
+        Ok(buf)
+    }
+}
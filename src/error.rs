@@ -0,0 +1,57 @@
+use std::error::Error;
+use std::fmt;
+
+use super::LengthField;
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum ParseError {
+    // Byte 0 is neither 0x80 nor 0x81
+    InvalidMagic(u8),
+    // Byte 1 isn't a known opcode, only returned by packet()
+    UnknownOpcode(u8),
+    // The total body length can't hold the key and extras
+    LengthMismatch { body_length: u32, key_length: u16, extras_length: u8 },
+    // A length field is over the ParserConfig limit
+    TooLarge { field: LengthField, length: u32, limit: u32 },
+    // More input is needed, at least this many bytes
+    Incomplete { needed: usize },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &ParseError::InvalidMagic(magic) => {
+                write!(f, "invalid magic 0x{:02x} at byte 0", magic)
+            }
+            &ParseError::UnknownOpcode(opcode) => {
+                write!(f, "unknown opcode 0x{:02x} at byte 1", opcode)
+            }
+            &ParseError::LengthMismatch { body_length, key_length, extras_length } => {
+                write!(f,
+                       "total body length {} at byte 8 is smaller than key length {} at byte 2 \
+                        plus extras length {} at byte 4",
+                       body_length,
+                       key_length,
+                       extras_length)
+            }
+            &ParseError::TooLarge { field, length, limit } => {
+                let (name, offset) = match field {
+                    LengthField::Key => ("key", 2),
+                    LengthField::Extras => ("extras", 4),
+                    LengthField::Body => ("total body", 8),
+                };
+                write!(f,
+                       "{} length {} at byte {} exceeds the limit of {}",
+                       name,
+                       length,
+                       offset,
+                       limit)
+            }
+            &ParseError::Incomplete { needed } => {
+                write!(f, "incomplete packet, {} more bytes needed", needed)
+            }
+        }
+    }
+}
+
+impl Error for ParseError {}
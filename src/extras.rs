@@ -0,0 +1,175 @@
+use std::io::{self, Write};
+
+use nom::{be_u32, be_u64, IResult};
+
+use super::{HeaderType, Opcode, Packet, ResponseStatus};
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Extras {
+    None,
+    // Set, Add and Replace requests
+    Storage { flags: u32, expiration: u32 },
+    // Increment and Decrement requests
+    Counter { delta: u64, initial: u64, expiration: u32 },
+    // Flush requests, the expiration is optional
+    Flush { expiration: Option<u32> },
+    // Touch, GetAndTouch and GetAndTouchQ requests
+    Touch { expiration: u32 },
+    Verbosity { level: u32 },
+    // Successful Get responses
+    Value { flags: u32 },
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct ExtrasLengthError {
+    pub opcode: Opcode,
+    pub expected: usize,
+    pub actual: usize,
+}
+
+named!(storage_extras<Extras>, do_parse!(
+  flags: be_u32 >>
+  expiration: be_u32 >>
+  (Extras::Storage { flags: flags, expiration: expiration })
+));
+
+named!(counter_extras<Extras>, do_parse!(
+  delta: be_u64 >>
+  initial: be_u64 >>
+  expiration: be_u32 >>
+  (Extras::Counter { delta: delta, initial: initial, expiration: expiration })
+));
+
+named!(flush_extras<Extras>, do_parse!(
+  expiration: be_u32 >>
+  (Extras::Flush { expiration: Some(expiration) })
+));
+
+named!(touch_extras<Extras>, do_parse!(
+  expiration: be_u32 >>
+  (Extras::Touch { expiration: expiration })
+));
+
+named!(verbosity_extras<Extras>, do_parse!(
+  level: be_u32 >>
+  (Extras::Verbosity { level: level })
+));
+
+named!(value_extras<Extras>, do_parse!(
+  flags: be_u32 >>
+  (Extras::Value { flags: flags })
+));
+
+fn request_extras(opcode: Opcode, input: &[u8]) -> Result<Extras, ExtrasLengthError> {
+    match opcode {
+        Opcode::Set | Opcode::SetQ | Opcode::Add | Opcode::AddQ | Opcode::Replace |
+        Opcode::ReplaceQ => exact(opcode, input, 8, storage_extras),
+        Opcode::Increment | Opcode::IncrementQ | Opcode::Decrement | Opcode::DecrementQ => {
+            exact(opcode, input, 20, counter_extras)
+        }
+        Opcode::Flush | Opcode::FlushQ if input.is_empty() => {
+            Ok(Extras::Flush { expiration: None })
+        }
+        Opcode::Flush | Opcode::FlushQ => exact(opcode, input, 4, flush_extras),
+        Opcode::Touch | Opcode::GetAndTouch | Opcode::GetAndTouchQ => {
+            exact(opcode, input, 4, touch_extras)
+        }
+        Opcode::Verbosity => exact(opcode, input, 4, verbosity_extras),
+        _ => none(opcode, input),
+    }
+}
+
+fn response_extras(opcode: Opcode, status: ResponseStatus, input: &[u8])
+                   -> Result<Extras, ExtrasLengthError> {
+    match opcode {
+        Opcode::Get | Opcode::GetQ | Opcode::GetK | Opcode::GetKQ | Opcode::GetAndTouch |
+        Opcode::GetAndTouchQ if status == ResponseStatus::NoError => {
+            exact(opcode, input, 4, value_extras)
+        }
+        _ => none(opcode, input),
+    }
+}
+
+fn exact(opcode: Opcode, input: &[u8], length: usize,
+         parser: fn(&[u8]) -> IResult<&[u8], Extras>)
+         -> Result<Extras, ExtrasLengthError> {
+    if input.len() != length {
+        return Err(ExtrasLengthError {
+            opcode: opcode,
+            expected: length,
+            actual: input.len(),
+        });
+    }
+    Ok(parser(input).unwrap().1)
+}
+
+fn none(opcode: Opcode, input: &[u8]) -> Result<Extras, ExtrasLengthError> {
+    if !input.is_empty() {
+        return Err(ExtrasLengthError {
+            opcode: opcode,
+            expected: 0,
+            actual: input.len(),
+        });
+    }
+    Ok(Extras::None)
+}
+
+pub fn decode_extras(header: &HeaderType, input: &[u8]) -> Result<Extras, ExtrasLengthError> {
+    match header {
+        &HeaderType::Request(ref r) => request_extras(r.opcode, input),
+        &HeaderType::Response(ref r) => response_extras(r.opcode, r.status, input),
+    }
+}
+
+impl<'a> Packet<'a, HeaderType> {
+    pub fn typed_extras(&self) -> Result<Extras, ExtrasLengthError> {
+        decode_extras(&self.header, self.extras)
+    }
+}
+
+impl Extras {
+    pub fn len(&self) -> usize {
+        match self {
+            &Extras::None => 0,
+            &Extras::Storage { .. } => 8,
+            &Extras::Counter { .. } => 20,
+            &Extras::Flush { expiration: None } => 0,
+            &Extras::Flush { expiration: Some(_) } => 4,
+            &Extras::Touch { .. } => 4,
+            &Extras::Verbosity { .. } => 4,
+            &Extras::Value { .. } => 4,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        match self {
+            &Extras::None => Ok(()),
+            &Extras::Storage { flags, expiration } => {
+                writer.write_all(&flags.to_be_bytes())?;
+                writer.write_all(&expiration.to_be_bytes())
+            }
+            &Extras::Counter { delta, initial, expiration } => {
+                writer.write_all(&delta.to_be_bytes())?;
+                writer.write_all(&initial.to_be_bytes())?;
+                writer.write_all(&expiration.to_be_bytes())
+            }
+            &Extras::Flush { expiration: None } => Ok(()),
+            &Extras::Flush { expiration: Some(expiration) } => {
+                writer.write_all(&expiration.to_be_bytes())
+            }
+            &Extras::Touch { expiration } => writer.write_all(&expiration.to_be_bytes()),
+            &Extras::Verbosity { level } => writer.write_all(&level.to_be_bytes()),
+            &Extras::Value { flags } => writer.write_all(&flags.to_be_bytes()),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.len());
+        self.write_to(&mut buf).unwrap();
+        buf
+    }
+}
@@ -0,0 +1,28 @@
+use std::io::{self, Read};
+
+use super::{packet_with_config, ParseError, ParserConfig, HEADER_LENGTH};
+
+// Reads exactly one packet into buf, replacing its contents, so it can be
+// handed to packet(). Limited to what memcached itself accepts, see
+// ParserConfig::default(), so a header can't make it buffer gigabytes.
+pub fn read_packet<R: Read>(reader: &mut R, buf: &mut Vec<u8>) -> io::Result<()> {
+    read_packet_with_config(reader, buf, &ParserConfig::default())
+}
+
+// The header is checked against the config before the body is read, so an
+// oversized or inconsistent packet is rejected without buffering it.
+pub fn read_packet_with_config<R: Read>(reader: &mut R, buf: &mut Vec<u8>,
+                                        config: &ParserConfig)
+                                        -> io::Result<()> {
+    buf.clear();
+    buf.resize(HEADER_LENGTH, 0);
+    reader.read_exact(&mut buf[..])?;
+    match packet_with_config(buf, config) {
+        Ok(_) | Err(ParseError::Incomplete { .. }) => (),
+        Err(err) => return Err(io::Error::new(io::ErrorKind::InvalidData, err)),
+    }
+    let body_length = ((buf[8] as usize) << 24) | ((buf[9] as usize) << 16) |
+                      ((buf[10] as usize) << 8) | (buf[11] as usize);
+    buf.resize(HEADER_LENGTH + body_length, 0);
+    reader.read_exact(&mut buf[HEADER_LENGTH..])
+}
@@ -0,0 +1,90 @@
+// The key hashes of libmemcached, giving the same values as its hashkit so
+// keys are distributed the same way.
+#[derive(Debug,Clone,Copy,Default,PartialEq,Eq)]
+pub enum KeyHash {
+    // Bob Jenkins' one-at-a-time, libmemcached's default
+    #[default]
+    OneAtATime,
+    // The first four bytes of the digest, little endian
+    Md5,
+    // Only bits 16 to 30 of the crc, as memcached clients have always done
+    Crc32,
+    Fnv1a32,
+    // MurmurHash3 x86_32, seeded with 0xdeadbeef times the key length
+    Murmur3,
+}
+
+impl KeyHash {
+    pub fn hash(&self, key: &[u8]) -> u32 {
+        match self {
+            &KeyHash::OneAtATime => one_at_a_time(key),
+            &KeyHash::Md5 => {
+                let digest = md5::compute(key).0;
+                (digest[3] as u32) << 24 | (digest[2] as u32) << 16 | (digest[1] as u32) << 8 |
+                digest[0] as u32
+            }
+            &KeyHash::Crc32 => (crc32(key) >> 16) & 0x7fff,
+            &KeyHash::Fnv1a32 => fnv1a_32(key),
+            &KeyHash::Murmur3 => murmur3_32(key, 0xdeadbeef_u32.wrapping_mul(key.len() as u32)),
+        }
+    }
+}
+
+fn one_at_a_time(key: &[u8]) -> u32 {
+    let mut hash: u32 = 0;
+    for &byte in key {
+        hash = hash.wrapping_add(byte as u32);
+        hash = hash.wrapping_add(hash << 10);
+        hash ^= hash >> 6;
+    }
+    hash = hash.wrapping_add(hash << 3);
+    hash ^= hash >> 11;
+    hash.wrapping_add(hash << 15)
+}
+
+fn crc32(key: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in key {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn fnv1a_32(key: &[u8]) -> u32 {
+    let mut hash: u32 = 0x811c9dc5;
+    for &byte in key {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(0x01000193);
+    }
+    hash
+}
+
+fn murmur3_32(key: &[u8], seed: u32) -> u32 {
+    const C1: u32 = 0xcc9e2d51;
+    const C2: u32 = 0x1b873593;
+    let mix = |k: u32| k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+
+    let mut hash = seed;
+    let mut blocks = key.chunks(4);
+    let tail = if key.len().is_multiple_of(4) { &[][..] } else { blocks.next_back().unwrap() };
+    for block in blocks {
+        let k = block[0] as u32 | (block[1] as u32) << 8 | (block[2] as u32) << 16 |
+                (block[3] as u32) << 24;
+        hash ^= mix(k);
+        hash = hash.rotate_left(13).wrapping_mul(5).wrapping_add(0xe6546b64);
+    }
+    if !tail.is_empty() {
+        let k = tail.iter().rev().fold(0, |k, &byte| k << 8 | byte as u32);
+        hash ^= mix(k);
+    }
+
+    hash ^= key.len() as u32;
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85ebca6b);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xc2b2ae35);
+    hash ^ hash >> 16
+}
@@ -0,0 +1,91 @@
+use super::{Distributor, Node};
+
+// libmemcached's MEMCACHED_POINTS_PER_SERVER_KETAMA, each md5 digest gives
+// four of them
+const POINTS_PER_SERVER: u32 = 160;
+const POINTS_PER_HASH: u32 = 4;
+
+const DEFAULT_PORT: u16 = 11211;
+
+// The alignment'th four bytes of the md5 digest of data, little endian.
+fn md5_point(data: &[u8], alignment: usize) -> u32 {
+    let digest = md5::compute(data).0;
+    let bytes = &digest[alignment * 4..alignment * 4 + 4];
+    (bytes[3] as u32) << 24 | (bytes[2] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[0] as u32
+}
+
+// Weighted ketama consistent hashing as done by libmemcached in its ketama
+// compatible mode, so keys land on the same node as with clients built on it.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Ketama {
+    // Points on the ring and the index of the node they belong to, by point
+    continuum: Vec<(u32, usize)>,
+}
+
+impl Ketama {
+    pub fn new(nodes: &[Node]) -> Ketama {
+        let total_weight: u32 = nodes.iter().map(|node| node.weight).sum();
+        let mut continuum = Vec::new();
+        for (index, node) in nodes.iter().enumerate() {
+            if node.weight == 0 {
+                continue;
+            }
+            // Worked out in single precision and in libmemcached's order, any
+            // other rounding could give a node a different number of points
+            let share = node.weight as f32 / total_weight as f32;
+            let points = share * POINTS_PER_SERVER as f32 / POINTS_PER_HASH as f32 *
+                         nodes.len() as f32;
+            let hashes = ((points as f64 + 0.0000000001) as f32).floor() as u32;
+            for i in 0..hashes {
+                // The default port is left out of the name of the point
+                let name = if node.port == DEFAULT_PORT {
+                    format!("{}-{}", node.host, i)
+                } else {
+                    format!("{}:{}-{}", node.host, node.port, i)
+                };
+                for alignment in 0..POINTS_PER_HASH as usize {
+                    continuum.push((md5_point(name.as_bytes(), alignment), index));
+                }
+            }
+        }
+        continuum.sort();
+        Ketama { continuum: continuum }
+    }
+
+    // Keys are hashed with md5 too, onto the first point at or after theirs.
+    pub fn hash(key: &[u8]) -> u32 {
+        md5_point(key, 0)
+    }
+
+    pub fn points(&self) -> usize {
+        self.continuum.len()
+    }
+
+    pub fn node_for_hash(&self, hash: u32) -> Option<usize> {
+        if self.continuum.is_empty() {
+            return None;
+        }
+        let position = match self.continuum.binary_search_by(|&(point, _)| point.cmp(&hash)) {
+            Ok(mut position) => {
+                // Several nodes can share a point, the first one wins
+                while position > 0 && self.continuum[position - 1].0 == hash {
+                    position -= 1;
+                }
+                position
+            }
+            Err(position) => position,
+        };
+        let position = if position == self.continuum.len() { 0 } else { position };
+        Some(self.continuum[position].1)
+    }
+}
+
+impl Distributor for Ketama {
+    fn node(&self, key: &[u8]) -> Option<usize> {
+        self.node_for_hash(Ketama::hash(key))
+    }
+
+    fn update(&mut self, nodes: &[Node]) {
+        *self = Ketama::new(nodes);
+    }
+}
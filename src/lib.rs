@@ -3,62 +3,280 @@
 extern crate alloc_system;
 extern crate test;
 
+extern crate bytes;
+extern crate md5;
 #[macro_use]
 extern crate nom;
+#[cfg(feature = "serde_json")]
+extern crate serde_json;
+#[cfg(feature = "tokio")]
+extern crate tokio_util;
 use nom::*;
 
-#[derive(Debug,PartialEq,Eq)]
+#[cfg(feature = "tokio")]
+mod async_client;
+#[cfg(feature = "tokio")]
+mod async_pool;
+mod client;
+mod cluster;
+#[cfg(feature = "tokio")]
+mod codec;
+mod command;
+mod config;
+mod decoder;
+mod distribution;
+mod encode;
+mod error;
+mod extras;
+mod framing;
+mod hash;
+mod ketama;
+mod owned;
+mod pool;
+mod sasl;
+mod server;
+mod store;
+mod vbucket;
+
+#[cfg(feature = "tokio")]
+pub use async_client::AsyncClient;
+#[cfg(feature = "tokio")]
+pub use async_pool::{AsyncPool, AsyncPooled};
+pub use client::{BatchFailure, Client, ClientError, Item};
+pub use cluster::{Cluster, FailoverConfig, Node};
+#[cfg(feature = "tokio")]
+pub use codec::MemcacheCodec;
+pub use command::{Command, CommandError, Reply};
+pub use config::{LengthField, ParserConfig, DEFAULT_MAX_EXTRAS_LENGTH, DEFAULT_MAX_KEY_LENGTH,
+                 DEFAULT_MAX_VALUE_LENGTH};
+pub use decoder::Decoder;
+pub use distribution::{Distributor, JumpHash, Modula, Rendezvous};
+pub use error::ParseError;
+pub use extras::{decode_extras, Extras, ExtrasLengthError};
+pub use framing::{read_packet, read_packet_with_config};
+pub use hash::KeyHash;
+pub use ketama::Ketama;
+pub use owned::OwnedPacket;
+pub use pool::{Pool, PoolConfig, PoolStats, Pooled};
+pub use sasl::{cram_md5_response, Authenticator, Mechanism, SaslError, Step};
+pub use server::{serve, serve_connection, Handler};
+pub use store::MemoryStore;
+pub use vbucket::{vbucket_id, VBucketClient, VBucketMap, VBucketMapError};
+
+pub const REQUEST_MAGIC: u8 = 0x80;
+pub const RESPONSE_MAGIC: u8 = 0x81;
+pub const HEADER_LENGTH: usize = 24;
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
 pub enum ResponseStatus {
-    NoError = 0x0000,
-    KeyNotFound = 0x0001,
-    KeyExists = 0x0002,
-    ValueTooLarge = 0x0003,
-    InvalidArguements = 0x0004,
-    NotStored = 0x0005,
-    NonNumeric = 0x0006,
-    WrongServer = 0x0007,
-    AuthenticationError = 0x0008,
-    AuthenticationContinue = 0x0009,
-    UnknownCommand = 0x0081,
-    OutOfMemory = 0x0082,
-    NotSupported = 0x0083,
-    InternalError = 0x0084,
-    Busy = 0x0085,
-    TemporaryFailure = 0x0086,
+    NoError,
+    KeyNotFound,
+    KeyExists,
+    ValueTooLarge,
+    InvalidArguements,
+    NotStored,
+    NonNumeric,
+    WrongServer,
+    AuthenticationError,
+    AuthenticationContinue,
+    UnknownCommand,
+    OutOfMemory,
+    NotSupported,
+    InternalError,
+    Busy,
+    TemporaryFailure,
+    // Any other status a server sends, so the response can still be framed
+    Unknown(u16),
+}
+
+impl From<u16> for ResponseStatus {
+    fn from(status: u16) -> ResponseStatus {
+        match status {
+            0x0000 => ResponseStatus::NoError,
+            0x0001 => ResponseStatus::KeyNotFound,
+            0x0002 => ResponseStatus::KeyExists,
+            0x0003 => ResponseStatus::ValueTooLarge,
+            0x0004 => ResponseStatus::InvalidArguements,
+            0x0005 => ResponseStatus::NotStored,
+            0x0006 => ResponseStatus::NonNumeric,
+            0x0007 => ResponseStatus::WrongServer,
+            // Older protocol documents list 0x0008 and 0x0009 for the SASL
+            // statuses, but memcached sends 0x0020 and 0x0021 and Couchbase uses
+            // 0x0008 and 0x0009 for other things, so those stay Unknown and
+            // encode back to the same bytes
+            0x0020 => ResponseStatus::AuthenticationError,
+            0x0021 => ResponseStatus::AuthenticationContinue,
+            0x0081 => ResponseStatus::UnknownCommand,
+            0x0082 => ResponseStatus::OutOfMemory,
+            0x0083 => ResponseStatus::NotSupported,
+            0x0084 => ResponseStatus::InternalError,
+            0x0085 => ResponseStatus::Busy,
+            0x0086 => ResponseStatus::TemporaryFailure,
+            other => ResponseStatus::Unknown(other),
+        }
+    }
+}
+
+impl From<ResponseStatus> for u16 {
+    fn from(status: ResponseStatus) -> u16 {
+        match status {
+            ResponseStatus::NoError => 0x0000,
+            ResponseStatus::KeyNotFound => 0x0001,
+            ResponseStatus::KeyExists => 0x0002,
+            ResponseStatus::ValueTooLarge => 0x0003,
+            ResponseStatus::InvalidArguements => 0x0004,
+            ResponseStatus::NotStored => 0x0005,
+            ResponseStatus::NonNumeric => 0x0006,
+            ResponseStatus::WrongServer => 0x0007,
+            ResponseStatus::AuthenticationError => 0x0020,
+            ResponseStatus::AuthenticationContinue => 0x0021,
+            ResponseStatus::UnknownCommand => 0x0081,
+            ResponseStatus::OutOfMemory => 0x0082,
+            ResponseStatus::NotSupported => 0x0083,
+            ResponseStatus::InternalError => 0x0084,
+            ResponseStatus::Busy => 0x0085,
+            ResponseStatus::TemporaryFailure => 0x0086,
+            ResponseStatus::Unknown(other) => other,
+        }
+    }
 }
 
-#[derive(Debug,PartialEq,Eq)]
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
 pub enum Opcode {
-    Get = 0x00,
-    Set = 0x01,
-    Add = 0x02,
-    Replace = 0x03,
-    Delete = 0x04,
-    Increment = 0x05,
-    Decrement = 0x06,
-    Quit = 0x07,
-    Flush = 0x08,
-    GetQ = 0x09,
-    Noop = 0x0A,
-    Version = 0x0B,
-    GetK = 0x0C,
-    GetKQ = 0x0D,
-    Append = 0x0E,
-    Prepend = 0x0F,
-    Stat = 0x10,
-    SetQ = 0x11,
-    AddQ = 0x12,
-    ReplaceQ = 0x13,
-    DeleteQ = 0x14,
-    IncrementQ = 0x15,
-    DecrementQ = 0x16,
-    QuitQ = 0x17,
-    FlushQ = 0x18,
-    AppendQ = 0x19,
-    PrependQ = 0x1A,
+    Get,
+    Set,
+    Add,
+    Replace,
+    Delete,
+    Increment,
+    Decrement,
+    Quit,
+    Flush,
+    GetQ,
+    Noop,
+    Version,
+    GetK,
+    GetKQ,
+    Append,
+    Prepend,
+    Stat,
+    SetQ,
+    AddQ,
+    ReplaceQ,
+    DeleteQ,
+    IncrementQ,
+    DecrementQ,
+    QuitQ,
+    FlushQ,
+    AppendQ,
+    PrependQ,
+    Verbosity,
+    Touch,
+    GetAndTouch,
+    GetAndTouchQ,
+    SaslListMechanisms,
+    SaslAuth,
+    SaslStep,
+    // Opcodes this crate doesn't know, only produced by packet_permissive()
+    Other(u8),
+}
+
+impl From<u8> for Opcode {
+    fn from(opcode: u8) -> Opcode {
+        match opcode {
+            0x00 => Opcode::Get,
+            0x01 => Opcode::Set,
+            0x02 => Opcode::Add,
+            0x03 => Opcode::Replace,
+            0x04 => Opcode::Delete,
+            0x05 => Opcode::Increment,
+            0x06 => Opcode::Decrement,
+            0x07 => Opcode::Quit,
+            0x08 => Opcode::Flush,
+            0x09 => Opcode::GetQ,
+            0x0A => Opcode::Noop,
+            0x0B => Opcode::Version,
+            0x0C => Opcode::GetK,
+            0x0D => Opcode::GetKQ,
+            0x0E => Opcode::Append,
+            0x0F => Opcode::Prepend,
+            0x10 => Opcode::Stat,
+            0x11 => Opcode::SetQ,
+            0x12 => Opcode::AddQ,
+            0x13 => Opcode::ReplaceQ,
+            0x14 => Opcode::DeleteQ,
+            0x15 => Opcode::IncrementQ,
+            0x16 => Opcode::DecrementQ,
+            0x17 => Opcode::QuitQ,
+            0x18 => Opcode::FlushQ,
+            0x19 => Opcode::AppendQ,
+            0x1A => Opcode::PrependQ,
+            0x1B => Opcode::Verbosity,
+            0x1C => Opcode::Touch,
+            0x1D => Opcode::GetAndTouch,
+            0x1E => Opcode::GetAndTouchQ,
+            0x20 => Opcode::SaslListMechanisms,
+            0x21 => Opcode::SaslAuth,
+            0x22 => Opcode::SaslStep,
+            other => Opcode::Other(other),
+        }
+    }
+}
+
+impl Opcode {
+    // Quiet commands only get a response when there's something to report,
+    // a miss for the gets or a success for the rest goes unanswered.
+    pub fn is_quiet(&self) -> bool {
+        matches!(self,
+                 Opcode::GetQ | Opcode::GetKQ | Opcode::SetQ | Opcode::AddQ | Opcode::ReplaceQ |
+                 Opcode::DeleteQ | Opcode::IncrementQ | Opcode::DecrementQ | Opcode::QuitQ |
+                 Opcode::FlushQ | Opcode::AppendQ | Opcode::PrependQ | Opcode::GetAndTouchQ)
+    }
+}
+
+impl From<Opcode> for u8 {
+    fn from(opcode: Opcode) -> u8 {
+        match opcode {
+            Opcode::Get => 0x00,
+            Opcode::Set => 0x01,
+            Opcode::Add => 0x02,
+            Opcode::Replace => 0x03,
+            Opcode::Delete => 0x04,
+            Opcode::Increment => 0x05,
+            Opcode::Decrement => 0x06,
+            Opcode::Quit => 0x07,
+            Opcode::Flush => 0x08,
+            Opcode::GetQ => 0x09,
+            Opcode::Noop => 0x0A,
+            Opcode::Version => 0x0B,
+            Opcode::GetK => 0x0C,
+            Opcode::GetKQ => 0x0D,
+            Opcode::Append => 0x0E,
+            Opcode::Prepend => 0x0F,
+            Opcode::Stat => 0x10,
+            Opcode::SetQ => 0x11,
+            Opcode::AddQ => 0x12,
+            Opcode::ReplaceQ => 0x13,
+            Opcode::DeleteQ => 0x14,
+            Opcode::IncrementQ => 0x15,
+            Opcode::DecrementQ => 0x16,
+            Opcode::QuitQ => 0x17,
+            Opcode::FlushQ => 0x18,
+            Opcode::AppendQ => 0x19,
+            Opcode::PrependQ => 0x1A,
+            Opcode::Verbosity => 0x1B,
+            Opcode::Touch => 0x1C,
+            Opcode::GetAndTouch => 0x1D,
+            Opcode::GetAndTouchQ => 0x1E,
+            Opcode::SaslListMechanisms => 0x20,
+            Opcode::SaslAuth => 0x21,
+            Opcode::SaslStep => 0x22,
+            Opcode::Other(other) => other,
+        }
+    }
 }
 
-#[derive(Debug,PartialEq,Eq)]
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
 pub enum DataType {
     Raw,
 }
@@ -66,50 +284,11 @@ pub enum DataType {
 named!(request, tag!(b"\x80"));
 named!(response, tag!(b"\x81"));
 
-named!(response_status<ResponseStatus>, switch!(take!(2),
-  b"\x00\x00" => value!(ResponseStatus::NoError)
- |b"\x00\x01" => value!(ResponseStatus::KeyNotFound)
- |b"\x00\x02" => value!(ResponseStatus::KeyExists)
- |b"\x00\x03" => value!(ResponseStatus::ValueTooLarge)
- |b"\x00\x04" => value!(ResponseStatus::InvalidArguements)
- |b"\x00\x05" => value!(ResponseStatus::NotStored)
- |b"\x00\x06" => value!(ResponseStatus::NonNumeric)
- |b"\x00\x81" => value!(ResponseStatus::UnknownCommand)
- |b"\x00\x82" => value!(ResponseStatus::OutOfMemory)
-));
+named!(response_status<ResponseStatus>, map!(be_u16, ResponseStatus::from));
+
+named!(opcode<Opcode>, map!(be_u8, Opcode::from));
 
-named!(opcode<Opcode>, switch!(take!(1),
-  b"\x00" => value!(Opcode::Get)
- |b"\x01" => value!(Opcode::Set)
- |b"\x02" => value!(Opcode::Add)
- |b"\x03" => value!(Opcode::Replace)
- |b"\x04" => value!(Opcode::Delete)
- |b"\x05" => value!(Opcode::Increment)
- |b"\x06" => value!(Opcode::Decrement)
- |b"\x07" => value!(Opcode::Quit)
- |b"\x08" => value!(Opcode::Flush)
- |b"\x09" => value!(Opcode::GetQ)
- |b"\x0A" => value!(Opcode::Noop)
- |b"\x0B" => value!(Opcode::Version)
- |b"\x0C" => value!(Opcode::GetK)
- |b"\x0D" => value!(Opcode::GetKQ)
- |b"\x0E" => value!(Opcode::Append)
- |b"\x0F" => value!(Opcode::Prepend)
- |b"\x10" => value!(Opcode::Stat)
- |b"\x11" => value!(Opcode::SetQ)
- |b"\x12" => value!(Opcode::AddQ)
- |b"\x13" => value!(Opcode::ReplaceQ)
- |b"\x14" => value!(Opcode::DeleteQ)
- |b"\x15" => value!(Opcode::IncrementQ)
- |b"\x16" => value!(Opcode::DecrementQ)
- |b"\x17" => value!(Opcode::QuitQ)
- |b"\x18" => value!(Opcode::FlushQ)
- |b"\x19" => value!(Opcode::AppendQ)
- |b"\x1A" => value!(Opcode::PrependQ)
-  )
-);
-
-#[derive(Debug,PartialEq,Eq)]
+#[derive(Debug,Clone,PartialEq,Eq)]
 pub struct ResponseHeader {
     pub opcode: Opcode,
     pub key_length: u16,
@@ -121,7 +300,7 @@ pub struct ResponseHeader {
     pub cas: u64,
 }
 
-#[derive(Debug,PartialEq,Eq)]
+#[derive(Debug,Clone,PartialEq,Eq)]
 pub struct RequestHeader {
     pub opcode: Opcode,
     pub key_length: u16,
@@ -133,6 +312,36 @@ pub struct RequestHeader {
     pub cas: u64,
 }
 
+impl ResponseHeader {
+    pub fn new(opcode: Opcode, status: ResponseStatus) -> ResponseHeader {
+        ResponseHeader {
+            opcode: opcode,
+            key_length: 0,
+            extras_length: 0,
+            data_type: DataType::Raw,
+            status: status,
+            body_length: 0,
+            opaque: 0,
+            cas: 0,
+        }
+    }
+}
+
+impl RequestHeader {
+    pub fn new(opcode: Opcode) -> RequestHeader {
+        RequestHeader {
+            opcode: opcode,
+            key_length: 0,
+            extras_length: 0,
+            data_type: DataType::Raw,
+            vbucket_id: 0,
+            body_length: 0,
+            opaque: 0,
+            cas: 0,
+        }
+    }
+}
+
 named!(header_fields<(u16, u8, &[u8], &[u8], u32, u32, u64)>, tuple!(
   be_u16,
   be_u8,
@@ -188,13 +397,13 @@ named!(header<HeaderType>, alt!(
   preceded!(response, response_header) | preceded!(request, request_header)
 ));
 
-#[derive(Debug,PartialEq,Eq)]
+#[derive(Debug,Clone,PartialEq,Eq)]
 pub enum HeaderType {
   Request(RequestHeader),
   Response(ResponseHeader)
 }
 
-#[derive(Debug,PartialEq,Eq)]
+#[derive(Debug,Clone,PartialEq,Eq)]
 pub struct Packet<'a, HeaderType> {
     pub header: HeaderType,
     pub extras: &'a [u8],
@@ -231,18 +440,63 @@ impl Header for HeaderType {
   }
 }
 
-pub fn packet<'a>(input: &'a [u8]) -> IResult<&[u8], Packet<HeaderType>> {
-    let (input, header): (_, _) = try_parse!(input, header);
-    let (input, extras)   = try_parse!(input, take!(header.extras_length() as usize));
-    let (input, key)   = try_parse!(input, take!(header.key_length() as usize));
-    let (input, body)   = try_parse!(input, take!(header.body_length() - header.key_length() as u32 - header.extras_length() as u32));
-    IResult::Done(input,
-                  Packet {
-                    header: header,
-                    extras: extras,
-                    key: key,
-                    body: body
-                  })
+pub fn packet<'a>(input: &'a [u8]) -> Result<(&'a [u8], Packet<'a, HeaderType>), ParseError> {
+    packet_with_config(input, &ParserConfig::unlimited())
+}
+
+// Like packet(), but unknown opcodes are kept as Opcode::Other instead of
+// failing the parse, the length fields are enough to frame the packet.
+pub fn packet_permissive<'a>(input: &'a [u8])
+                             -> Result<(&'a [u8], Packet<'a, HeaderType>), ParseError> {
+    let config = ParserConfig { allow_unknown_opcodes: true, ..ParserConfig::unlimited() };
+    packet_with_config(input, &config)
+}
+
+// Reports problems as early as possible: a bad magic or opcode is an error
+// even when the rest of the header hasn't arrived yet, and the length limits
+// are checked before waiting for the body.
+pub fn packet_with_config<'a>(input: &'a [u8], config: &ParserConfig)
+                              -> Result<(&'a [u8], Packet<'a, HeaderType>), ParseError> {
+    if let Some(&magic) = input.first() {
+        if magic != REQUEST_MAGIC && magic != RESPONSE_MAGIC {
+            return Err(ParseError::InvalidMagic(magic));
+        }
+    }
+    if let Some(&code) = input.get(1) {
+        if let Opcode::Other(code) = Opcode::from(code) {
+            if !config.allow_unknown_opcodes {
+                return Err(ParseError::UnknownOpcode(code));
+            }
+        }
+    }
+    if input.len() < HEADER_LENGTH {
+        return Err(ParseError::Incomplete { needed: HEADER_LENGTH - input.len() });
+    }
+    // Everything the header parser could reject has been checked above
+    let (input, header) = header(input).unwrap();
+    let fixed_length = header.key_length() as u32 + header.extras_length() as u32;
+    if header.body_length() < fixed_length {
+        return Err(ParseError::LengthMismatch {
+            body_length: header.body_length(),
+            key_length: header.key_length(),
+            extras_length: header.extras_length(),
+        });
+    }
+    config.check(&header)?;
+    let body_length = header.body_length() as usize;
+    if input.len() < body_length {
+        return Err(ParseError::Incomplete { needed: body_length - input.len() });
+    }
+    let (extras, input) = input.split_at(header.extras_length() as usize);
+    let (key, input) = input.split_at(header.key_length() as usize);
+    let (body, input) = input.split_at(body_length - fixed_length as usize);
+    Ok((input,
+        Packet {
+            header: header,
+            extras: extras,
+            key: key,
+            body: body
+        }))
 }
 
 #[cfg(test)]
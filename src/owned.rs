@@ -0,0 +1,80 @@
+use std::io::{self, Write};
+
+use bytes::Bytes;
+
+use super::{decode_extras, packet_with_config, Extras, ExtrasLengthError, Header, HeaderType,
+            Packet, ParseError, ParserConfig, HEADER_LENGTH};
+
+// A packet that owns its contents, so it can outlive the buffer it was read
+// into or be sent to another task. extras, key and body are slices of one
+// shared buffer.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct OwnedPacket {
+    pub header: HeaderType,
+    pub extras: Bytes,
+    pub key: Bytes,
+    pub body: Bytes,
+}
+
+impl OwnedPacket {
+    // frame holds the whole packet, header included, and has been checked
+    // against the header's lengths already.
+    pub(crate) fn from_frame(header: HeaderType, frame: Bytes) -> OwnedPacket {
+        let extras_end = HEADER_LENGTH + header.extras_length() as usize;
+        let key_end = extras_end + header.key_length() as usize;
+        OwnedPacket {
+            extras: frame.slice(HEADER_LENGTH..extras_end),
+            key: frame.slice(extras_end..key_end),
+            body: frame.slice(key_end..),
+            header: header,
+        }
+    }
+
+    // Splits the first packet off the front of buf without copying it.
+    pub fn split_from(buf: &mut Bytes, config: &ParserConfig) -> Result<OwnedPacket, ParseError> {
+        let header = packet_with_config(buf, config)?.1.header;
+        let frame = buf.split_to(HEADER_LENGTH + header.body_length() as usize);
+        Ok(OwnedPacket::from_frame(header, frame))
+    }
+
+    pub fn packet<'a>(&'a self) -> Packet<'a, HeaderType> {
+        Packet {
+            header: self.header.clone(),
+            extras: &self.extras,
+            key: &self.key,
+            body: &self.body,
+        }
+    }
+
+    pub fn typed_extras(&self) -> Result<Extras, ExtrasLengthError> {
+        decode_extras(&self.header, &self.extras)
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.packet().write_to(writer)
+    }
+
+    pub fn encode(&self) -> io::Result<Vec<u8>> {
+        self.packet().encode()
+    }
+}
+
+// Copies the contents into a single allocation.
+impl<'a> From<Packet<'a, HeaderType>> for OwnedPacket {
+    fn from(packet: Packet<'a, HeaderType>) -> OwnedPacket {
+        let mut contents = Vec::with_capacity(packet.extras.len() + packet.key.len() +
+                                              packet.body.len());
+        contents.extend_from_slice(packet.extras);
+        contents.extend_from_slice(packet.key);
+        contents.extend_from_slice(packet.body);
+        let contents = Bytes::from(contents);
+        let key_start = packet.extras.len();
+        let body_start = key_start + packet.key.len();
+        OwnedPacket {
+            header: packet.header,
+            extras: contents.slice(..key_start),
+            key: contents.slice(key_start..body_start),
+            body: contents.slice(body_start..),
+        }
+    }
+}
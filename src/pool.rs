@@ -0,0 +1,250 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use super::{Client, ClientError};
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct PoolConfig {
+    // Connections open at once, in use or idle
+    pub max_size: usize,
+    // Idle connections older than this are closed rather than reused
+    pub idle_timeout: Option<Duration>,
+    // How long a checkout waits for a connection when all are in use,
+    // forever if None
+    pub checkout_timeout: Option<Duration>,
+    // Limits on connecting and on each read or write, so a server that hangs
+    // can't hold up a checkout. Pool applies them in its own connector, a
+    // custom one has to set its own. AsyncPool applies connect_timeout to any
+    // connector and io_timeout to its health checks.
+    pub connect_timeout: Option<Duration>,
+    pub io_timeout: Option<Duration>,
+    // Whether idle connections are checked with a Noop before reuse
+    pub health_check: bool,
+}
+
+impl Default for PoolConfig {
+    fn default() -> PoolConfig {
+        PoolConfig {
+            max_size: 8,
+            idle_timeout: Some(Duration::from_secs(60)),
+            checkout_timeout: Some(Duration::from_secs(5)),
+            connect_timeout: Some(Duration::from_secs(1)),
+            io_timeout: Some(Duration::from_secs(1)),
+            health_check: true,
+        }
+    }
+}
+
+impl PoolConfig {
+    pub(crate) fn expired(&self, idle_since: Instant) -> bool {
+        self.idle_timeout.is_some_and(|timeout| idle_since.elapsed() >= timeout)
+    }
+}
+
+// Counters since the pool was made, and the connections open right now.
+#[derive(Debug,Clone,Copy,Default,PartialEq,Eq)]
+pub struct PoolStats {
+    pub checkouts: u64,
+    // Checkouts that had to wait for a connection to be returned
+    pub waits: u64,
+    pub timeouts: u64,
+    pub connects: u64,
+    pub failed_connects: u64,
+    pub failed_health_checks: u64,
+    pub expired: u64,
+    // Connections dropped after a connection error
+    pub broken: u64,
+    pub open: usize,
+    pub idle: usize,
+}
+
+pub(crate) fn checkout_timeout() -> ClientError {
+    ClientError::Io(io::Error::new(io::ErrorKind::TimedOut,
+                                   "timed out waiting for a pooled connection"))
+}
+
+struct PoolState<S> {
+    idle: VecDeque<(Client<S>, Instant)>,
+    stats: PoolStats,
+}
+
+// A bounded pool of blocking clients for one server. Checkouts reuse the
+// most recently returned connection, and open a new one while there's room.
+pub struct Pool<S = TcpStream> {
+    config: PoolConfig,
+    connect: Box<dyn Fn() -> Result<Client<S>, ClientError> + Send + Sync>,
+    state: Mutex<PoolState<S>>,
+    returned: Condvar,
+}
+
+impl Pool<TcpStream> {
+    pub fn new(address: &str, config: PoolConfig) -> Pool<TcpStream> {
+        let address = address.to_string();
+        Pool::with_connector(config, move || {
+            Ok(Client::connect_with_timeouts(&address[..],
+                                             config.connect_timeout,
+                                             config.io_timeout)?)
+        })
+    }
+}
+
+impl<S: Read + Write> Pool<S> {
+    pub fn with_connector<F>(config: PoolConfig, connect: F) -> Pool<S>
+        where F: Fn() -> Result<Client<S>, ClientError> + Send + Sync + 'static
+    {
+        Pool {
+            config: config,
+            connect: Box::new(connect),
+            state: Mutex::new(PoolState {
+                idle: VecDeque::new(),
+                stats: PoolStats::default(),
+            }),
+            returned: Condvar::new(),
+        }
+    }
+
+    pub fn config(&self) -> &PoolConfig {
+        &self.config
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        let state = self.state.lock().unwrap();
+        PoolStats { idle: state.idle.len(), ..state.stats }
+    }
+
+    pub fn get(&self) -> Result<Pooled<'_, S>, ClientError> {
+        let deadline = self.config.checkout_timeout.map(|timeout| Instant::now() + timeout);
+        let mut waited = false;
+        loop {
+            let idle = {
+                let mut state = self.state.lock().unwrap();
+                loop {
+                    if let Some(idle) = state.idle.pop_back() {
+                        break Some(idle);
+                    }
+                    if state.stats.open < self.config.max_size {
+                        state.stats.open += 1;
+                        break None;
+                    }
+                    if !waited {
+                        state.stats.waits += 1;
+                        waited = true;
+                    }
+                    state = match deadline {
+                        Some(deadline) => {
+                            let now = Instant::now();
+                            if now >= deadline {
+                                state.stats.timeouts += 1;
+                                return Err(checkout_timeout());
+                            }
+                            self.returned.wait_timeout(state, deadline - now).unwrap().0
+                        }
+                        None => self.returned.wait(state).unwrap(),
+                    };
+                }
+            };
+            // Connecting and health checks happen without holding the lock
+            let (client, connected) = match idle {
+                Some((_, since)) if self.config.expired(since) => {
+                    self.close(|stats| stats.expired += 1);
+                    continue;
+                }
+                Some((mut client, _)) => {
+                    if self.config.health_check && client.noop().is_err() {
+                        self.close(|stats| stats.failed_health_checks += 1);
+                        continue;
+                    }
+                    (client, false)
+                }
+                None => {
+                    match (self.connect)() {
+                        Ok(client) => (client, true),
+                        Err(err) => {
+                            self.close(|stats| stats.failed_connects += 1);
+                            return Err(err);
+                        }
+                    }
+                }
+            };
+            let mut state = self.state.lock().unwrap();
+            state.stats.checkouts += 1;
+            if connected {
+                state.stats.connects += 1;
+            }
+            return Ok(Pooled {
+                pool: self,
+                client: Some(client),
+                broken: false,
+            });
+        }
+    }
+
+    // Runs f with a pooled connection, dropping the connection rather than
+    // returning it if f fails with a connection error.
+    pub fn run<T, F>(&self, f: F) -> Result<T, ClientError>
+        where F: FnOnce(&mut Client<S>) -> Result<T, ClientError>
+    {
+        let mut client = self.get()?;
+        let result = f(&mut client);
+        if let Err(ref err) = result {
+            if err.is_connection_error() {
+                client.mark_broken();
+            }
+        }
+        result
+    }
+
+    fn close<F: FnOnce(&mut PoolStats)>(&self, count: F) {
+        let mut state = self.state.lock().unwrap();
+        state.stats.open -= 1;
+        count(&mut state.stats);
+        self.returned.notify_one();
+    }
+}
+
+// A checked out connection, returned to the pool when dropped.
+pub struct Pooled<'a, S: Read + Write = TcpStream> {
+    pool: &'a Pool<S>,
+    client: Option<Client<S>>,
+    broken: bool,
+}
+
+impl<'a, S: Read + Write> Pooled<'a, S> {
+    // Closes the connection instead of returning it, after an error that
+    // left it unusable.
+    pub fn mark_broken(&mut self) {
+        self.broken = true;
+    }
+}
+
+impl<'a, S: Read + Write> Deref for Pooled<'a, S> {
+    type Target = Client<S>;
+
+    fn deref(&self) -> &Client<S> {
+        self.client.as_ref().unwrap()
+    }
+}
+
+impl<'a, S: Read + Write> DerefMut for Pooled<'a, S> {
+    fn deref_mut(&mut self) -> &mut Client<S> {
+        self.client.as_mut().unwrap()
+    }
+}
+
+impl<'a, S: Read + Write> Drop for Pooled<'a, S> {
+    fn drop(&mut self) {
+        let client = self.client.take().unwrap();
+        if self.broken {
+            drop(client);
+            self.pool.close(|stats| stats.broken += 1);
+            return;
+        }
+        let mut state = self.pool.state.lock().unwrap();
+        state.idle.push_back((client, Instant::now()));
+        self.pool.returned.notify_one();
+    }
+}
@@ -0,0 +1,206 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use super::{packet, read_packet, Command, ParseError, Reply, ResponseStatus};
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Mechanism {
+    Plain,
+    CramMd5,
+}
+
+impl Mechanism {
+    pub fn name(&self) -> &'static str {
+        match self {
+            &Mechanism::Plain => "PLAIN",
+            &Mechanism::CramMd5 => "CRAM-MD5",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SaslError {
+    Io(io::Error),
+    Parse(ParseError),
+    // The server sent something that isn't a valid reply at this point
+    Protocol(String),
+    // None of the mechanisms the server offered can be used
+    Unsupported(String),
+    Failed(ResponseStatus, String),
+}
+
+impl From<io::Error> for SaslError {
+    fn from(err: io::Error) -> SaslError {
+        SaslError::Io(err)
+    }
+}
+
+impl From<ParseError> for SaslError {
+    fn from(err: ParseError) -> SaslError {
+        SaslError::Parse(err)
+    }
+}
+
+impl fmt::Display for SaslError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &SaslError::Io(ref err) => write!(f, "sasl i/o error: {}", err),
+            &SaslError::Parse(ref err) => write!(f, "sasl parse error: {}", err),
+            &SaslError::Protocol(ref message) => write!(f, "sasl protocol error: {}", message),
+            &SaslError::Unsupported(ref offered) => {
+                write!(f, "no usable sasl mechanism, server offered \"{}\"", offered)
+            }
+            &SaslError::Failed(status, ref message) => {
+                write!(f, "sasl authentication failed with {:?}: {}", status, message)
+            }
+        }
+    }
+}
+
+impl Error for SaslError {}
+
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum Step {
+    Send(Command),
+    Done,
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+enum State {
+    Start,
+    ListingMechanisms,
+    Authenticating(Mechanism),
+    Stepping,
+    Done,
+}
+
+// Drives a SASL exchange one reply at a time, leaving the i/o to the caller:
+// start() gives the first command to send and every reply is handed to
+// receive() until it says Step::Done.
+#[derive(Debug,Clone)]
+pub struct Authenticator {
+    username: Vec<u8>,
+    password: Vec<u8>,
+    mechanism: Option<Mechanism>,
+    state: State,
+}
+
+impl Authenticator {
+    pub fn new(username: &str, password: &str) -> Authenticator {
+        Authenticator {
+            username: username.as_bytes().to_vec(),
+            password: password.as_bytes().to_vec(),
+            mechanism: None,
+            state: State::Start,
+        }
+    }
+
+    // Without a mechanism the strongest one the server offers is used.
+    pub fn with_mechanism(mut self, mechanism: Mechanism) -> Authenticator {
+        self.mechanism = Some(mechanism);
+        self
+    }
+
+    pub fn start(&mut self) -> Command {
+        self.state = State::ListingMechanisms;
+        Command::SaslListMechanisms
+    }
+
+    pub fn receive(&mut self, reply: Reply) -> Result<Step, SaslError> {
+        match (self.state, reply) {
+            (State::ListingMechanisms, Reply::Mechanisms(offered)) => {
+                let mechanism = self.choose(&offered)?;
+                self.state = State::Authenticating(mechanism);
+                let data = match mechanism {
+                    Mechanism::Plain => {
+                        let mut data = vec![0];
+                        data.extend_from_slice(&self.username);
+                        data.push(0);
+                        data.extend_from_slice(&self.password);
+                        data
+                    }
+                    Mechanism::CramMd5 => Vec::new(),
+                };
+                Ok(Step::Send(Command::SaslAuth {
+                    mechanism: mechanism.name().as_bytes().to_vec(),
+                    data: data,
+                }))
+            }
+            (State::Authenticating(_), Reply::Authenticated(_)) |
+            (State::Stepping, Reply::Authenticated(_)) => {
+                self.state = State::Done;
+                Ok(Step::Done)
+            }
+            (State::Authenticating(Mechanism::CramMd5), Reply::Challenge(challenge)) => {
+                self.state = State::Stepping;
+                Ok(Step::Send(Command::SaslStep {
+                    mechanism: Mechanism::CramMd5.name().as_bytes().to_vec(),
+                    data: cram_md5_response(&self.username, &self.password, &challenge),
+                }))
+            }
+            (_, Reply::Error(status, message)) => Err(SaslError::Failed(status, message)),
+            (state, reply) => {
+                Err(SaslError::Protocol(format!("unexpected {:?} while {:?}", reply, state)))
+            }
+        }
+    }
+
+    // Runs the whole exchange on a connection that has nothing else in flight.
+    pub fn authenticate<S: Read + Write>(&mut self, stream: &mut S) -> Result<(), SaslError> {
+        let mut buf = Vec::new();
+        let mut command = self.start();
+        loop {
+            command.write_to(0, stream)?;
+            stream.flush()?;
+            read_packet(stream, &mut buf)?;
+            let (_, response) = packet(&buf)?;
+            let reply = Reply::from_packet(&response)
+                .map_err(|err| SaslError::Protocol(format!("{:?}", err)))?;
+            match self.receive(reply)? {
+                Step::Send(next) => command = next,
+                Step::Done => return Ok(()),
+            }
+        }
+    }
+
+    fn choose(&self, offered: &str) -> Result<Mechanism, SaslError> {
+        let supported = |mechanism: Mechanism| {
+            offered.split_whitespace().any(|name| name == mechanism.name())
+        };
+        let candidates = match self.mechanism {
+            Some(mechanism) => vec![mechanism],
+            None => vec![Mechanism::CramMd5, Mechanism::Plain],
+        };
+        candidates.into_iter()
+            .find(|&mechanism| supported(mechanism))
+            .ok_or_else(|| SaslError::Unsupported(offered.to_string()))
+    }
+}
+
+fn hmac_md5(key: &[u8], message: &[u8]) -> [u8; 16] {
+    let mut block = [0u8; 64];
+    if key.len() > block.len() {
+        block[..16].copy_from_slice(&md5::compute(key).0);
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner = md5::Context::new();
+    inner.consume(block.iter().map(|b| b ^ 0x36).collect::<Vec<u8>>());
+    inner.consume(message);
+    let mut outer = md5::Context::new();
+    outer.consume(block.iter().map(|b| b ^ 0x5c).collect::<Vec<u8>>());
+    outer.consume(inner.compute().0);
+    outer.compute().0
+}
+
+// RFC 2195: the username, a space and the hex encoded HMAC-MD5 of the
+// challenge keyed with the password.
+pub fn cram_md5_response(username: &[u8], password: &[u8], challenge: &[u8]) -> Vec<u8> {
+    let mut response = username.to_vec();
+    response.push(b' ');
+    for byte in hmac_md5(password, challenge).iter() {
+        response.extend_from_slice(format!("{:02x}", byte).as_bytes());
+    }
+    response
+}
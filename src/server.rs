@@ -0,0 +1,151 @@
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::thread;
+
+use super::{packet_with_config, read_packet_with_config, Command, CommandError, HeaderType,
+            Opcode, ParserConfig, Reply, ResponseStatus};
+
+// Answers the commands of a memcached compatible server. Handlers are shared
+// by every connection, so they take &self.
+pub trait Handler {
+    fn handle(&self, command: Command) -> Reply;
+
+    // Stat is answered by one reply per statistic, an empty group asks for
+    // the general ones.
+    fn stats(&self, _group: &[u8]) -> Vec<(String, String)> {
+        Vec::new()
+    }
+}
+
+impl<H: Handler + ?Sized> Handler for &H {
+    fn handle(&self, command: Command) -> Reply {
+        (**self).handle(command)
+    }
+
+    fn stats(&self, group: &[u8]) -> Vec<(String, String)> {
+        (**self).stats(group)
+    }
+}
+
+impl<H: Handler + ?Sized> Handler for Arc<H> {
+    fn handle(&self, command: Command) -> Reply {
+        (**self).handle(command)
+    }
+
+    fn stats(&self, group: &[u8]) -> Vec<(String, String)> {
+        (**self).stats(group)
+    }
+}
+
+// Quiet commands leave out the replies nobody is waiting for, misses for the
+// gets and successes for the rest.
+fn suppressed(opcode: Opcode, reply: &Reply) -> bool {
+    if !opcode.is_quiet() {
+        return false;
+    }
+    match reply {
+        &Reply::Error(ResponseStatus::KeyNotFound, _) => {
+            opcode == Opcode::GetQ || opcode == Opcode::GetKQ || opcode == Opcode::GetAndTouchQ
+        }
+        &Reply::Error(..) | &Reply::Value { .. } => false,
+        _ => true,
+    }
+}
+
+// Serves one connection until the client quits or hangs up. Replies are
+// buffered while more pipelined requests are waiting to be read.
+pub fn serve_connection<H, R, W>(handler: &H, reader: R, writer: W) -> io::Result<()>
+    where H: Handler,
+          R: Read,
+          W: Write
+{
+    let config = ParserConfig { allow_unknown_opcodes: true, ..ParserConfig::default() };
+    let mut reader = BufReader::new(reader);
+    let mut writer = BufWriter::new(writer);
+    let mut buf = Vec::new();
+    loop {
+        if reader.buffer().is_empty() {
+            writer.flush()?;
+        }
+        match read_packet_with_config(&mut reader, &mut buf, &config) {
+            Ok(()) => (),
+            Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) => return Err(err),
+        }
+        let (_, request) = packet_with_config(&buf, &config)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let (opcode, opaque) = match request.header {
+            HeaderType::Request(ref h) => (h.opcode, h.opaque),
+            HeaderType::Response(_) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                          "client sent a response packet"))
+            }
+        };
+        let command = match Command::from_packet(&request) {
+            Ok(command) => command,
+            Err(CommandError::UnsupportedOpcode(_)) => {
+                let reply = Reply::Error(ResponseStatus::UnknownCommand,
+                                         "Unknown command".to_string());
+                reply.write_to(opcode, opaque, &mut writer)?;
+                continue;
+            }
+            Err(_) => {
+                let reply = Reply::Error(ResponseStatus::InvalidArguements,
+                                         "Invalid arguments".to_string());
+                reply.write_to(opcode, opaque, &mut writer)?;
+                continue;
+            }
+        };
+        match command {
+            Command::Stat { ref key } => {
+                for (name, value) in handler.stats(key) {
+                    Reply::Stat { key: name.into_bytes(), value: value.into_bytes() }
+                        .write_to(opcode, opaque, &mut writer)?;
+                }
+                Reply::Stat { key: Vec::new(), value: Vec::new() }
+                    .write_to(opcode, opaque, &mut writer)?;
+                continue;
+            }
+            Command::Quit { .. } => {
+                if !opcode.is_quiet() {
+                    Reply::Quit.write_to(opcode, opaque, &mut writer)?;
+                }
+                return writer.flush();
+            }
+            _ => (),
+        }
+        let key = command.key().to_vec();
+        let reply = match handler.handle(command) {
+            // GetK and GetKQ return the key even if the handler didn't
+            Reply::Value { flags, cas, key: returned, value } => {
+                Reply::Value {
+                    flags: flags,
+                    cas: cas,
+                    key: if returned.is_empty() { key } else { returned },
+                    value: value,
+                }
+            }
+            reply => reply,
+        };
+        if !suppressed(opcode, &reply) {
+            reply.write_to(opcode, opaque, &mut writer)?;
+        }
+    }
+}
+
+// Accepts connections forever, serving each on its own thread.
+pub fn serve<H>(listener: TcpListener, handler: Arc<H>) -> io::Result<()>
+    where H: Handler + Send + Sync + 'static
+{
+    for stream in listener.incoming() {
+        let stream = stream?;
+        stream.set_nodelay(true)?;
+        let handler = Arc::clone(&handler);
+        thread::spawn(move || {
+            let reader = stream.try_clone()?;
+            serve_connection(&handler, reader, stream)
+        });
+    }
+    Ok(())
+}
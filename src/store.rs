@@ -0,0 +1,556 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{Command, Handler, Reply, ResponseStatus, DEFAULT_MAX_VALUE_LENGTH};
+
+// Expirations up to 30 days are relative to now, longer ones are unix times
+const RELATIVE_EXPIRATION_LIMIT: u32 = 60 * 60 * 24 * 30;
+
+// Incr and decr with this expiration fail on a missing key instead of
+// creating it
+const NO_AUTO_CREATE: u32 = 0xffffffff;
+
+// What an item costs on top of its key and value, about the size of
+// memcached's item header
+const ITEM_OVERHEAD: usize = 48;
+
+const SMALLEST_CHUNK: usize = 96;
+
+fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn error(status: ResponseStatus) -> Reply {
+    let message = match status {
+        ResponseStatus::KeyNotFound => "Not found",
+        ResponseStatus::KeyExists => "Data exists for key.",
+        ResponseStatus::ValueTooLarge => "Too large.",
+        ResponseStatus::NotStored => "Not stored.",
+        ResponseStatus::NonNumeric => "Non-numeric server-side value for incr or decr",
+        ResponseStatus::OutOfMemory => "Out of memory",
+        _ => "Unknown command",
+    };
+    Reply::Error(status, message.to_string())
+}
+
+// The slab class an item of size bytes goes in and the size of its chunks.
+// Without a growth factor every item is its own size and in the one class.
+fn slab_class(size: usize, growth_factor: Option<f64>) -> (usize, usize) {
+    let growth_factor = match growth_factor {
+        Some(growth_factor) => growth_factor,
+        None => return (0, size),
+    };
+    let mut class = 0;
+    let mut chunk = SMALLEST_CHUNK;
+    while chunk < size {
+        class += 1;
+        chunk = ((chunk as f64 * growth_factor).ceil() as usize + 7) & !7;
+    }
+    (class, chunk)
+}
+
+#[derive(Debug,Clone)]
+struct Item {
+    value: Vec<u8>,
+    flags: u32,
+    cas: u64,
+    // Unix time the item expires at, if ever
+    expires: Option<u64>,
+    stored: u64,
+    // Set when the item is linked
+    size: usize,
+    class: usize,
+    touched: u64,
+}
+
+impl Item {
+    fn new(value: Vec<u8>, flags: u32, expires: Option<u64>, stored: u64) -> Item {
+        Item {
+            value: value,
+            flags: flags,
+            cas: 0,
+            expires: expires,
+            stored: stored,
+            size: 0,
+            class: 0,
+            touched: 0,
+        }
+    }
+
+    fn expired(&self, now: u64) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+}
+
+#[derive(Debug,Clone,Default)]
+struct SlabClass {
+    // Keys by when they were last used, oldest first
+    lru: BTreeMap<u64, Vec<u8>>,
+    // Bytes taken by its items
+    used: usize,
+    evicted: u64,
+}
+
+#[derive(Debug,Default)]
+struct Stats {
+    total_items: u64,
+    cmd_get: u64,
+    cmd_set: u64,
+    cmd_flush: u64,
+    cmd_touch: u64,
+    get_hits: u64,
+    get_misses: u64,
+    touch_hits: u64,
+    touch_misses: u64,
+    evictions: u64,
+    reclaimed: u64,
+}
+
+#[derive(Debug,Default)]
+struct State {
+    items: HashMap<Vec<u8>, Item>,
+    classes: Vec<SlabClass>,
+    limit: Option<usize>,
+    growth_factor: Option<f64>,
+    used: usize,
+    last_cas: u64,
+    last_touch: u64,
+    // A delayed flush, items stored up to then are dropped once it's reached
+    flush_at: Option<u64>,
+    stats: Stats,
+}
+
+impl State {
+    fn expire(&mut self, now: u64) {
+        if let Some(flush_at) = self.flush_at {
+            if flush_at <= now {
+                let flushed: Vec<Vec<u8>> = self.items
+                    .iter()
+                    .filter(|&(_, item)| item.stored <= flush_at)
+                    .map(|(key, _)| key.clone())
+                    .collect();
+                for key in flushed {
+                    self.unlink(&key);
+                }
+                self.flush_at = None;
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        self.items.clear();
+        // Eviction counts are kept, like memcached's
+        for class in &mut self.classes {
+            class.lru.clear();
+            class.used = 0;
+        }
+        self.used = 0;
+    }
+
+    // The item under key, unless it has expired. Finding it makes it the most
+    // recently used.
+    fn live(&mut self, key: &[u8], now: u64) -> Option<&mut Item> {
+        if self.items.get(key)?.expired(now) {
+            self.unlink(key);
+            return None;
+        }
+        self.last_touch += 1;
+        let item = self.items.get_mut(key).unwrap();
+        let lru = &mut self.classes[item.class].lru;
+        let key = lru.remove(&item.touched).unwrap();
+        lru.insert(self.last_touch, key);
+        item.touched = self.last_touch;
+        Some(item)
+    }
+
+    fn unlink(&mut self, key: &[u8]) -> Option<Item> {
+        let item = self.items.remove(key)?;
+        let class = &mut self.classes[item.class];
+        class.lru.remove(&item.touched);
+        class.used -= item.size;
+        self.used -= item.size;
+        Some(item)
+    }
+
+    // Stores the item in place of any other under key, evicting the least
+    // recently used items of its slab class until it fits in the memory limit.
+    // When it can't fit the store is left as it was.
+    fn link(&mut self, key: Vec<u8>, mut item: Item, now: u64) -> Result<u64, ResponseStatus> {
+        let (class, size) =
+            slab_class(key.len() + item.value.len() + ITEM_OVERHEAD, self.growth_factor);
+        if self.limit.is_some_and(|limit| size > limit) {
+            return Err(ResponseStatus::OutOfMemory);
+        }
+        if self.classes.len() <= class {
+            self.classes.resize(class + 1, SlabClass::default());
+        }
+        // The item it replaces, which is freed but not evicted
+        let (replaced, replaced_lru) = match self.items.get(&key) {
+            Some(old) if old.class == class => (old.size, Some(old.touched)),
+            Some(old) => (old.size, None),
+            None => (0, None),
+        };
+        if let Some(limit) = self.limit {
+            let evictable = self.classes[class].used - replaced_lru.map_or(0, |_| replaced);
+            if self.used - replaced + size > limit + evictable {
+                return Err(ResponseStatus::OutOfMemory);
+            }
+            while self.used - replaced + size > limit {
+                let oldest = match self.classes[class].lru.iter()
+                    .find(|&(&touched, _)| Some(touched) != replaced_lru) {
+                    Some((_, oldest)) => oldest.clone(),
+                    None => return Err(ResponseStatus::OutOfMemory),
+                };
+                let evicted = self.unlink(&oldest).unwrap();
+                if evicted.expired(now) {
+                    self.stats.reclaimed += 1;
+                } else {
+                    self.stats.evictions += 1;
+                    self.classes[class].evicted += 1;
+                }
+            }
+        }
+        self.unlink(&key);
+        self.last_cas += 1;
+        self.last_touch += 1;
+        item.cas = self.last_cas;
+        item.size = size;
+        item.class = class;
+        item.touched = self.last_touch;
+        self.classes[class].lru.insert(item.touched, key.clone());
+        self.classes[class].used += size;
+        self.items.insert(key, item);
+        self.used += size;
+        self.stats.total_items += 1;
+        Ok(self.last_cas)
+    }
+
+    fn store(&mut self, key: Vec<u8>, value: Vec<u8>, flags: u32, expiration: u32, now: u64)
+             -> Reply {
+        let item = Item::new(value, flags, expires_at(expiration, now), now);
+        match self.link(key, item, now) {
+            Ok(cas) => Reply::Stored { cas: cas },
+            Err(status) => error(status),
+        }
+    }
+}
+
+fn expires_at(expiration: u32, now: u64) -> Option<u64> {
+    match expiration {
+        0 => None,
+        relative if relative <= RELATIVE_EXPIRATION_LIMIT => Some(now + relative as u64),
+        absolute => Some(absolute as u64),
+    }
+}
+
+// A cas of 0 matches any item
+fn cas_matches(item: &Item, cas: u64) -> bool {
+    cas == 0 || cas == item.cas
+}
+
+// An in-memory memcached, answering every command with the status a real
+// server would. Useful as a test double, it's a single hash map behind a lock.
+pub struct MemoryStore {
+    state: Mutex<State>,
+    clock: Box<dyn Fn() -> u64 + Send + Sync>,
+    started: u64,
+    max_value_length: u32,
+}
+
+impl Default for MemoryStore {
+    fn default() -> MemoryStore {
+        MemoryStore::new()
+    }
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::with_clock(unix_time)
+    }
+
+    // The clock returns the current unix time in seconds, for tests that
+    // need to control expiration.
+    pub fn with_clock<F>(clock: F) -> MemoryStore
+        where F: Fn() -> u64 + Send + Sync + 'static
+    {
+        let started = clock();
+        MemoryStore {
+            state: Mutex::new(State::default()),
+            clock: Box::new(clock),
+            started: started,
+            max_value_length: DEFAULT_MAX_VALUE_LENGTH,
+        }
+    }
+
+    pub fn with_max_value_length(mut self, max_value_length: u32) -> MemoryStore {
+        self.max_value_length = max_value_length;
+        self
+    }
+
+    // Evicts the least recently used items to keep what's stored, counting a
+    // fixed overhead per item, within limit bytes.
+    pub fn with_memory_limit(mut self, limit: usize) -> MemoryStore {
+        self.state.get_mut().unwrap().limit = Some(limit);
+        self
+    }
+
+    // Rounds items up to memcached-like slab chunks, each chunk size
+    // growth_factor times the last. Items only evict others of their own
+    // size class, so a store full of small items has no room for large ones.
+    pub fn with_slab_classes(mut self, growth_factor: f64) -> MemoryStore {
+        assert!(growth_factor > 1.0, "slab classes need a growth factor above 1");
+        self.state.get_mut().unwrap().growth_factor = Some(growth_factor);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn get(&self, state: &mut State, key: &[u8], touch: Option<u32>, now: u64) -> Reply {
+        state.stats.cmd_get += 1;
+        if touch.is_some() {
+            state.stats.cmd_touch += 1;
+        }
+        let reply = match state.live(key, now) {
+            Some(item) => {
+                if let Some(expiration) = touch {
+                    item.expires = expires_at(expiration, now);
+                }
+                Reply::Value {
+                    flags: item.flags,
+                    cas: item.cas,
+                    key: Vec::new(),
+                    value: item.value.clone(),
+                }
+            }
+            None => error(ResponseStatus::KeyNotFound),
+        };
+        let hit = reply.status() == ResponseStatus::NoError;
+        if hit {
+            state.stats.get_hits += 1;
+        } else {
+            state.stats.get_misses += 1;
+        }
+        if touch.is_some() {
+            if hit {
+                state.stats.touch_hits += 1;
+            } else {
+                state.stats.touch_misses += 1;
+            }
+        }
+        reply
+    }
+
+    fn concat(&self, state: &mut State, key: Vec<u8>, value: Vec<u8>, cas: u64, append: bool,
+              now: u64)
+              -> Reply {
+        state.stats.cmd_set += 1;
+        let max_value_length = self.max_value_length as usize;
+        let item = match state.live(&key, now) {
+            Some(ref item) if !cas_matches(item, cas) => return error(ResponseStatus::KeyExists),
+            Some(ref item) if item.value.len() + value.len() > max_value_length => {
+                return error(ResponseStatus::ValueTooLarge)
+            }
+            Some(item) => {
+                let joined = if append {
+                    [&item.value[..], &value[..]].concat()
+                } else {
+                    [&value[..], &item.value[..]].concat()
+                };
+                Item::new(joined, item.flags, item.expires, item.stored)
+            }
+            None => return error(ResponseStatus::NotStored),
+        };
+        match state.link(key, item, now) {
+            Ok(cas) => Reply::Stored { cas: cas },
+            Err(status) => error(status),
+        }
+    }
+
+    fn counter(&self, state: &mut State, key: Vec<u8>, apply: &dyn Fn(u64) -> u64,
+               initial: u64, expiration: u32, now: u64)
+               -> Reply {
+        let (value, item) = match state.live(&key, now) {
+            Some(item) => {
+                let current = match String::from_utf8_lossy(&item.value).trim().parse() {
+                    Ok(current) => current,
+                    Err(_) => return error(ResponseStatus::NonNumeric),
+                };
+                let value = apply(current);
+                let digits = value.to_string().into_bytes();
+                (value, Item::new(digits, item.flags, item.expires, item.stored))
+            }
+            None if expiration == NO_AUTO_CREATE => return error(ResponseStatus::KeyNotFound),
+            None => {
+                let digits = initial.to_string().into_bytes();
+                (initial, Item::new(digits, 0, expires_at(expiration, now), now))
+            }
+        };
+        match state.link(key, item, now) {
+            Ok(cas) => Reply::Counter { value: value, cas: cas },
+            Err(status) => error(status),
+        }
+    }
+}
+
+impl Handler for MemoryStore {
+    fn handle(&self, command: Command) -> Reply {
+        let now = (self.clock)();
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        state.expire(now);
+        match command {
+            Command::Set { ref value, .. } |
+            Command::Add { ref value, .. } |
+            Command::Replace { ref value, .. } |
+            Command::Append { ref value, .. } |
+            Command::Prepend { ref value, .. } if value.len() as u64 >
+                                                  self.max_value_length as u64 => {
+                state.stats.cmd_set += 1;
+                return error(ResponseStatus::ValueTooLarge);
+            }
+            _ => (),
+        }
+        match command {
+            Command::Get { key, .. } => self.get(state, &key, None, now),
+            Command::GetAndTouch { key, expiration, .. } => {
+                self.get(state, &key, Some(expiration), now)
+            }
+            Command::Set { key, value, flags, expiration, cas, .. } => {
+                state.stats.cmd_set += 1;
+                if cas != 0 {
+                    match state.live(&key, now) {
+                        Some(ref item) if !cas_matches(item, cas) => {
+                            return error(ResponseStatus::KeyExists)
+                        }
+                        Some(_) => (),
+                        None => return error(ResponseStatus::KeyNotFound),
+                    }
+                }
+                state.store(key, value, flags, expiration, now)
+            }
+            Command::Add { key, value, flags, expiration, .. } => {
+                state.stats.cmd_set += 1;
+                if state.live(&key, now).is_some() {
+                    return error(ResponseStatus::KeyExists);
+                }
+                state.store(key, value, flags, expiration, now)
+            }
+            Command::Replace { key, value, flags, expiration, cas, .. } => {
+                state.stats.cmd_set += 1;
+                match state.live(&key, now) {
+                    Some(ref item) if !cas_matches(item, cas) => {
+                        return error(ResponseStatus::KeyExists)
+                    }
+                    Some(_) => (),
+                    None => return error(ResponseStatus::KeyNotFound),
+                }
+                state.store(key, value, flags, expiration, now)
+            }
+            Command::Append { key, value, cas, .. } => {
+                self.concat(state, key, value, cas, true, now)
+            }
+            Command::Prepend { key, value, cas, .. } => {
+                self.concat(state, key, value, cas, false, now)
+            }
+            Command::Delete { key, cas, .. } => {
+                match state.live(&key, now) {
+                    Some(ref item) if !cas_matches(item, cas) => {
+                        return error(ResponseStatus::KeyExists)
+                    }
+                    Some(_) => (),
+                    None => return error(ResponseStatus::KeyNotFound),
+                }
+                state.unlink(&key);
+                // Like memcached, deletes are answered without a CAS
+                Reply::Deleted { cas: 0 }
+            }
+            Command::Incr { key, delta, initial, expiration, .. } => {
+                self.counter(state, key, &|value| value.wrapping_add(delta), initial, expiration,
+                             now)
+            }
+            Command::Decr { key, delta, initial, expiration, .. } => {
+                self.counter(state, key, &|value| value.saturating_sub(delta), initial,
+                             expiration, now)
+            }
+            Command::Touch { key, expiration } => {
+                state.stats.cmd_touch += 1;
+                let cas = match state.live(&key, now) {
+                    Some(item) => {
+                        item.expires = expires_at(expiration, now);
+                        item.cas
+                    }
+                    None => {
+                        state.stats.touch_misses += 1;
+                        return error(ResponseStatus::KeyNotFound);
+                    }
+                };
+                state.stats.touch_hits += 1;
+                Reply::Touched { cas: cas }
+            }
+            Command::Flush { expiration, .. } => {
+                state.stats.cmd_flush += 1;
+                match expiration.and_then(|expiration| expires_at(expiration, now)) {
+                    Some(flush_at) if flush_at > now => state.flush_at = Some(flush_at),
+                    _ => {
+                        state.flush();
+                        state.flush_at = None;
+                    }
+                }
+                Reply::Flushed
+            }
+            Command::Noop => Reply::Noop,
+            Command::Version => Reply::Version(env!("CARGO_PKG_VERSION").to_string()),
+            Command::Verbosity { .. } => Reply::Verbosity,
+            _ => error(ResponseStatus::UnknownCommand),
+        }
+    }
+
+    fn stats(&self, group: &[u8]) -> Vec<(String, String)> {
+        let now = (self.clock)();
+        let mut state = self.state.lock().unwrap();
+        state.expire(now);
+        match group {
+            b"" => {
+                let stats = &state.stats;
+                vec![("pid".to_string(), ::std::process::id().to_string()),
+                     ("uptime".to_string(), now.saturating_sub(self.started).to_string()),
+                     ("time".to_string(), now.to_string()),
+                     ("version".to_string(), env!("CARGO_PKG_VERSION").to_string()),
+                     ("limit_maxbytes".to_string(), state.limit.unwrap_or(0).to_string()),
+                     ("bytes".to_string(), state.used.to_string()),
+                     ("curr_items".to_string(), state.items.len().to_string()),
+                     ("total_items".to_string(), stats.total_items.to_string()),
+                     ("cmd_get".to_string(), stats.cmd_get.to_string()),
+                     ("cmd_set".to_string(), stats.cmd_set.to_string()),
+                     ("cmd_flush".to_string(), stats.cmd_flush.to_string()),
+                     ("cmd_touch".to_string(), stats.cmd_touch.to_string()),
+                     ("get_hits".to_string(), stats.get_hits.to_string()),
+                     ("get_misses".to_string(), stats.get_misses.to_string()),
+                     ("touch_hits".to_string(), stats.touch_hits.to_string()),
+                     ("touch_misses".to_string(), stats.touch_misses.to_string()),
+                     ("evictions".to_string(), stats.evictions.to_string()),
+                     ("reclaimed".to_string(), stats.reclaimed.to_string())]
+            }
+            // Like memcached's slab ids, classes are numbered from 1
+            b"items" => {
+                let mut stats = Vec::new();
+                for (i, class) in state.classes.iter().enumerate() {
+                    if class.lru.is_empty() && class.evicted == 0 {
+                        continue;
+                    }
+                    stats.push((format!("items:{}:number", i + 1), class.lru.len().to_string()));
+                    stats.push((format!("items:{}:evicted", i + 1), class.evicted.to_string()));
+                }
+                stats
+            }
+            _ => Vec::new(),
+        }
+    }
+}
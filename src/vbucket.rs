@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+#[cfg(feature = "serde_json")]
+use serde_json::{self, Value};
+
+use super::{Client, ClientError, Item, KeyHash, ResponseStatus};
+
+// The vBucket a key belongs to, out of vbuckets, a power of two. Like
+// libvbucket this is the memcached style crc32 of the key, masked.
+pub fn vbucket_id(key: &[u8], vbuckets: usize) -> u16 {
+    (KeyHash::Crc32.hash(key) as usize & (vbuckets - 1)) as u16
+}
+
+#[derive(Debug)]
+pub enum VBucketMapError {
+    #[cfg(feature = "serde_json")]
+    Json(serde_json::Error),
+    // The JSON isn't a vBucket server map, or one that can be used
+    Invalid(String),
+}
+
+#[cfg(feature = "serde_json")]
+impl From<serde_json::Error> for VBucketMapError {
+    fn from(err: serde_json::Error) -> VBucketMapError {
+        VBucketMapError::Json(err)
+    }
+}
+
+impl fmt::Display for VBucketMapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            #[cfg(feature = "serde_json")]
+            &VBucketMapError::Json(ref err) => write!(f, "invalid JSON: {}", err),
+            &VBucketMapError::Invalid(ref message) => write!(f, "invalid vBucket map: {}", message),
+        }
+    }
+}
+
+impl Error for VBucketMapError {}
+
+fn invalid<T>(message: &str) -> Result<T, VBucketMapError> {
+    Err(VBucketMapError::Invalid(message.to_string()))
+}
+
+// Which server holds each vBucket, as "host:port" indexes into the server
+// list. Every vBucket lists its master first, then its replicas.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct VBucketMap {
+    servers: Vec<String>,
+    vbuckets: Vec<Vec<Option<usize>>>,
+}
+
+impl VBucketMap {
+    pub fn new(servers: Vec<String>, vbuckets: Vec<Vec<Option<usize>>>)
+               -> Result<VBucketMap, VBucketMapError> {
+        if !vbuckets.len().is_power_of_two() || vbuckets.len() > 0x10000 {
+            return invalid("the number of vBuckets has to be a power of two up to 65536");
+        }
+        let known = |server: &Option<usize>| server.is_none_or(|server| server < servers.len());
+        if !vbuckets.iter().all(|vbucket| vbucket.iter().all(&known)) {
+            return invalid("a vBucket refers to a server that isn't listed");
+        }
+        Ok(VBucketMap {
+            servers: servers,
+            vbuckets: vbuckets,
+        })
+    }
+
+    // Reads the "vBucketServerMap" of a Couchbase bucket config, or a bare
+    // server map. -1 stands for a missing server, and the hash has to be CRC.
+    #[cfg(feature = "serde_json")]
+    pub fn from_json(json: &str) -> Result<VBucketMap, VBucketMapError> {
+        let config: Value = serde_json::from_str(json)?;
+        let map = config.get("vBucketServerMap").unwrap_or(&config);
+        match map.get("hashAlgorithm").map(|hash| hash.as_str()) {
+            None => (),
+            Some(Some(hash)) if hash.eq_ignore_ascii_case("crc") => (),
+            Some(_) => return invalid("only the CRC hash algorithm is supported"),
+        }
+        let servers = match map.get("serverList").and_then(Value::as_array) {
+            Some(servers) => servers,
+            None => return invalid("no serverList"),
+        };
+        let servers = servers.iter()
+            .map(|server| server.as_str().map(str::to_string))
+            .collect::<Option<Vec<String>>>();
+        let servers = match servers {
+            Some(servers) => servers,
+            None => return invalid("servers have to be strings"),
+        };
+        let table = match map.get("vBucketMap").and_then(Value::as_array) {
+            Some(table) => table,
+            None => return invalid("no vBucketMap"),
+        };
+        let mut vbuckets = Vec::with_capacity(table.len());
+        for vbucket in table {
+            let vbucket = vbucket.as_array().and_then(|vbucket| {
+                vbucket.iter().map(Value::as_i64).collect::<Option<Vec<i64>>>()
+            });
+            let vbucket = match vbucket {
+                Some(vbucket) => vbucket,
+                None => return invalid("vBuckets have to be lists of server indexes"),
+            };
+            vbuckets.push(vbucket.into_iter()
+                .map(|server| if server < 0 { None } else { Some(server as usize) })
+                .collect());
+        }
+        VBucketMap::new(servers, vbuckets)
+    }
+
+    pub fn servers(&self) -> &[String] {
+        &self.servers
+    }
+
+    pub fn len(&self) -> usize {
+        self.vbuckets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vbuckets.is_empty()
+    }
+
+    pub fn vbucket(&self, key: &[u8]) -> u16 {
+        vbucket_id(key, self.vbuckets.len())
+    }
+
+    pub fn master(&self, vbucket: u16) -> Option<usize> {
+        self.vbuckets[vbucket as usize].first().cloned().unwrap_or(None)
+    }
+
+    pub fn replicas(&self, vbucket: u16) -> &[Option<usize>] {
+        let servers = &self.vbuckets[vbucket as usize];
+        if servers.is_empty() { servers } else { &servers[1..] }
+    }
+
+    // A server answered that it isn't the master of the vBucket. As libvbucket
+    // does, the next server takes over as master until the map is refreshed.
+    // Returns the new master.
+    pub fn found_incorrect_master(&mut self, vbucket: u16, wrong: usize) -> Option<usize> {
+        let servers = self.servers.len();
+        let vbucket = &mut self.vbuckets[vbucket as usize];
+        if vbucket.is_empty() {
+            vbucket.push(None);
+        }
+        if vbucket[0] == Some(wrong) {
+            vbucket[0] = Some((wrong + 1) % servers);
+        }
+        vbucket[0]
+    }
+}
+
+// A map sent along with a WrongServer error, when it can be read.
+#[cfg(feature = "serde_json")]
+fn map_in(message: &str) -> Option<VBucketMap> {
+    VBucketMap::from_json(message).ok()
+}
+
+#[cfg(not(feature = "serde_json"))]
+fn map_in(_message: &str) -> Option<VBucketMap> {
+    None
+}
+
+type Connector<S> = Box<dyn FnMut(&str) -> Result<Client<S>, ClientError>>;
+
+// A blocking client for a vBucket-aware cluster. Keys go to the master of
+// their vBucket, with the vBucket in the request. When a server answers
+// WrongServer the map is refreshed, from the config sent along with the
+// error if there is one, and the request retried.
+pub struct VBucketClient<S = TcpStream> {
+    map: VBucketMap,
+    clients: HashMap<String, Client<S>>,
+    connect: Connector<S>,
+}
+
+impl VBucketClient<TcpStream> {
+    // Servers are connected to when first needed.
+    pub fn new(map: VBucketMap) -> VBucketClient<TcpStream> {
+        VBucketClient::with_connector(map, |server| Ok(Client::connect(server)?))
+    }
+}
+
+impl<S: Read + Write> VBucketClient<S> {
+    pub fn with_connector<F>(map: VBucketMap, connect: F) -> VBucketClient<S>
+        where F: FnMut(&str) -> Result<Client<S>, ClientError> + 'static
+    {
+        VBucketClient {
+            map: map,
+            clients: HashMap::new(),
+            connect: Box::new(connect),
+        }
+    }
+
+    pub fn map(&self) -> &VBucketMap {
+        &self.map
+    }
+
+    // Connections to servers that are still in the map are kept.
+    pub fn update_map(&mut self, map: VBucketMap) {
+        self.clients.retain(|server, _| map.servers.contains(server));
+        for client in self.clients.values_mut() {
+            client.set_vbuckets(map.len());
+        }
+        self.map = map;
+    }
+
+    pub fn get(&mut self, key: &[u8]) -> Result<Option<Item>, ClientError> {
+        self.with_master(key, |client| client.get(key))
+    }
+
+    pub fn set(&mut self, key: &[u8], value: &[u8], flags: u32, expiration: u32)
+               -> Result<u64, ClientError> {
+        self.with_master(key, |client| client.set(key, value, flags, expiration))
+    }
+
+    pub fn add(&mut self, key: &[u8], value: &[u8], flags: u32, expiration: u32)
+               -> Result<u64, ClientError> {
+        self.with_master(key, |client| client.add(key, value, flags, expiration))
+    }
+
+    pub fn replace(&mut self, key: &[u8], value: &[u8], flags: u32, expiration: u32)
+                   -> Result<u64, ClientError> {
+        self.with_master(key, |client| client.replace(key, value, flags, expiration))
+    }
+
+    pub fn append(&mut self, key: &[u8], value: &[u8]) -> Result<u64, ClientError> {
+        self.with_master(key, |client| client.append(key, value))
+    }
+
+    pub fn prepend(&mut self, key: &[u8], value: &[u8]) -> Result<u64, ClientError> {
+        self.with_master(key, |client| client.prepend(key, value))
+    }
+
+    pub fn delete(&mut self, key: &[u8]) -> Result<(), ClientError> {
+        self.with_master(key, |client| client.delete(key))
+    }
+
+    pub fn incr(&mut self, key: &[u8], delta: u64, initial: u64, expiration: u32)
+                -> Result<u64, ClientError> {
+        self.with_master(key, |client| client.incr(key, delta, initial, expiration))
+    }
+
+    pub fn decr(&mut self, key: &[u8], delta: u64, initial: u64, expiration: u32)
+                -> Result<u64, ClientError> {
+        self.with_master(key, |client| client.decr(key, delta, initial, expiration))
+    }
+
+    // Tries each server at most once before giving up with the last
+    // WrongServer error.
+    fn with_master<T, F>(&mut self, key: &[u8], mut f: F) -> Result<T, ClientError>
+        where F: FnMut(&mut Client<S>) -> Result<T, ClientError>
+    {
+        let mut attempts = 0;
+        loop {
+            let vbucket = self.map.vbucket(key);
+            let master = self.map.master(vbucket).ok_or(ClientError::NoServers)?;
+            let server = self.map.servers[master].clone();
+            if !self.clients.contains_key(&server) {
+                let mut client = (self.connect)(&server)?;
+                client.set_vbuckets(self.map.len());
+                self.clients.insert(server.clone(), client);
+            }
+            let message = match f(self.clients.get_mut(&server).unwrap()) {
+                Err(ClientError::Status(ResponseStatus::WrongServer, message)) => message,
+                result => return result,
+            };
+            attempts += 1;
+            if attempts >= self.map.servers.len() {
+                return Err(ClientError::Status(ResponseStatus::WrongServer, message));
+            }
+            match map_in(&message) {
+                Some(map) => self.update_map(map),
+                None => {
+                    self.map.found_incorrect_master(vbucket, master);
+                }
+            }
+        }
+    }
+}
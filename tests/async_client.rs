@@ -0,0 +1,205 @@
+#![cfg(feature = "tokio")]
+extern crate futures;
+extern crate memcache_protocol;
+extern crate tokio;
+extern crate tokio_util;
+use memcache_protocol::*;
+
+use futures::{SinkExt, StreamExt};
+use tokio::io::DuplexStream;
+use tokio_util::codec::Framed;
+
+type Server = Framed<DuplexStream, MemcacheCodec>;
+
+fn connect() -> (AsyncClient, Server) {
+    let (client, server) = tokio::io::duplex(4096);
+    (AsyncClient::new(client), Framed::new(server, MemcacheCodec::new()))
+}
+
+async fn receive(server: &mut Server) -> (u32, Command) {
+    let request = server.next().await.unwrap().unwrap();
+    let opaque = match request.header {
+        HeaderType::Request(ref h) => h.opaque,
+        _ => panic!(),
+    };
+    (opaque, Command::from_packet(&request.packet()).unwrap())
+}
+
+async fn reply(server: &mut Server, opcode: Opcode, opaque: u32, reply: Reply) {
+    let response = reply.encode(opcode, opaque).unwrap();
+    let (_, response) = packet(&response).unwrap();
+    server.send(response).await.unwrap();
+}
+
+fn value(value: &str) -> Reply {
+    Reply::Value {
+        flags: 0,
+        cas: 1,
+        key: Vec::new(),
+        value: value.as_bytes().to_vec(),
+    }
+}
+
+#[tokio::test]
+async fn responses_out_of_order() {
+    let (client, mut server) = connect();
+    let server = async move {
+        let mut requests = Vec::new();
+        for _ in 0..3 {
+            requests.push(receive(&mut server).await);
+        }
+        for (opaque, command) in requests.into_iter().rev() {
+            let key = String::from_utf8(command.key().to_vec()).unwrap();
+            reply(&mut server, Opcode::Get, opaque, value(&key.to_uppercase())).await;
+        }
+        server
+    };
+    let (a, b, c, _) =
+        futures::join!(client.get(b"a"), client.get(b"b"), client.get(b"c"), server);
+    assert_eq!(b"A".to_vec(), a.unwrap().unwrap().value);
+    assert_eq!(b"B".to_vec(), b.unwrap().unwrap().value);
+    assert_eq!(b"C".to_vec(), c.unwrap().unwrap().value);
+}
+
+#[tokio::test]
+async fn quiet_requests_without_a_response() {
+    let (client, mut server) = connect();
+    let get = |key: &str| {
+        let key = key.as_bytes().to_vec();
+        client.request(Command::Get { key: key, quiet: true, return_key: false })
+    };
+    let server = async move {
+        let (_, _) = receive(&mut server).await;
+        let (hit, _) = receive(&mut server).await;
+        let (failed, _) = receive(&mut server).await;
+        let (noop, _) = receive(&mut server).await;
+        reply(&mut server, Opcode::GetQ, hit, value("hit")).await;
+        let error = Reply::Error(ResponseStatus::ValueTooLarge, "Too large.".to_string());
+        reply(&mut server, Opcode::GetQ, failed, error).await;
+        reply(&mut server, Opcode::Noop, noop, Reply::Noop).await;
+        server
+    };
+    let (miss, hit, failed, noop, _) =
+        futures::join!(get("miss"), get("hit"), get("failed"), client.noop(), server);
+    assert_eq!(None, miss.unwrap());
+    assert_eq!(Some(value("hit")), hit.unwrap());
+    assert_eq!(Some(ResponseStatus::ValueTooLarge), failed.unwrap_err().status());
+    noop.unwrap();
+}
+
+#[tokio::test]
+async fn get_multi() {
+    let (client, mut server) = connect();
+    let server = async move {
+        loop {
+            let (opaque, command) = receive(&mut server).await;
+            match command {
+                Command::Get { ref key, quiet: true, return_key: true } if key[0] == b'h' => {
+                    let hit = Reply::Value {
+                        flags: 0,
+                        cas: 1,
+                        key: key.clone(),
+                        value: key.to_ascii_uppercase(),
+                    };
+                    reply(&mut server, Opcode::GetKQ, opaque, hit).await;
+                }
+                Command::Get { .. } => (),
+                Command::Noop => {
+                    reply(&mut server, Opcode::Noop, opaque, Reply::Noop).await;
+                    return server;
+                }
+                command => panic!("{:?}", command),
+            }
+        }
+    };
+    let keys = ["hit1", "miss1", "hit2", "miss2"];
+    let (items, _) = futures::join!(client.get_multi(&keys), server);
+    let items = items.unwrap();
+    assert_eq!(2, items.len());
+    assert_eq!(b"HIT1".to_vec(), items[&b"hit1"[..]].value);
+    assert_eq!(b"HIT2".to_vec(), items[&b"hit2"[..]].value);
+}
+
+#[tokio::test]
+async fn batch() {
+    let (client, mut server) = connect();
+    let server = async move {
+        loop {
+            let (opaque, command) = receive(&mut server).await;
+            assert!(command.opcode().is_quiet() || command == Command::Noop);
+            match command {
+                Command::Set { ref key, .. } if key == b"large" => {
+                    let error = "Too large.".to_string();
+                    let error = Reply::Error(ResponseStatus::ValueTooLarge, error);
+                    reply(&mut server, Opcode::SetQ, opaque, error).await;
+                }
+                Command::Noop => {
+                    reply(&mut server, Opcode::Noop, opaque, Reply::Noop).await;
+                    return server;
+                }
+                _ => (),
+            }
+        }
+    };
+    let items = vec![("small", "value"), ("large", "value"), ("other", "value")];
+    let (failures, _) = futures::join!(client.set_multi(items, 0, 0), server);
+    assert_eq!(vec![BatchFailure {
+                        index: 1,
+                        status: ResponseStatus::ValueTooLarge,
+                        message: "Too large.".to_string(),
+                    }],
+               failures.unwrap());
+}
+
+#[tokio::test]
+async fn stats_span_several_responses() {
+    let (client, mut server) = connect();
+    let server = async move {
+        let (opaque, command) = receive(&mut server).await;
+        assert_eq!(Command::Stat { key: Vec::new() }, command);
+        for &(key, value) in [("pid", "1234"), ("uptime", "42"), ("", "")].iter() {
+            let stat = Reply::Stat {
+                key: key.as_bytes().to_vec(),
+                value: value.as_bytes().to_vec(),
+            };
+            reply(&mut server, Opcode::Stat, opaque, stat).await;
+        }
+        server
+    };
+    let (stats, _) = futures::join!(client.stats(b""), server);
+    let stats = stats.unwrap();
+    assert_eq!(2, stats.len());
+    assert_eq!("1234", stats["pid"]);
+}
+
+#[tokio::test]
+async fn closed_connection_fails_pending_requests() {
+    let (client, mut server) = connect();
+    let server = async move {
+        receive(&mut server).await;
+        drop(server);
+    };
+    let (version, _) = futures::join!(client.version(), server);
+    match version {
+        Err(ClientError::Io(ref err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => (),
+        other => panic!("{:?}", other),
+    }
+    assert!(client.noop().await.is_err());
+}
+
+#[tokio::test]
+async fn handles_share_the_connection() {
+    let (client, mut server) = connect();
+    let other = client.clone();
+    let server = async move {
+        let (first, _) = receive(&mut server).await;
+        let (second, _) = receive(&mut server).await;
+        reply(&mut server, Opcode::Increment, first, Reply::Counter { value: 1, cas: 0 }).await;
+        reply(&mut server, Opcode::Increment, second, Reply::Counter { value: 2, cas: 0 }).await;
+        server
+    };
+    let (first, second, _) = futures::join!(client.incr(b"counter", 1, 1, 0),
+                                            other.incr(b"counter", 1, 1, 0),
+                                            server);
+    assert_eq!(3, first.unwrap() + second.unwrap());
+}
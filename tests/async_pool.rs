@@ -0,0 +1,109 @@
+#![cfg(feature = "tokio")]
+extern crate memcache_protocol;
+extern crate tokio;
+use memcache_protocol::*;
+
+use std::io;
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+fn start(max_size: usize) -> AsyncPool {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || serve(listener, Arc::new(MemoryStore::new())));
+    let config = PoolConfig {
+        max_size: max_size,
+        checkout_timeout: Some(Duration::from_millis(100)),
+        ..PoolConfig::default()
+    };
+    AsyncPool::new(&address, config)
+}
+
+#[tokio::test]
+async fn connections_are_reused() {
+    let pool = start(2);
+    pool.get().await.unwrap().set(b"Hello", b"World", 0, 0).await.unwrap();
+    {
+        let first = pool.get().await.unwrap();
+        let second = pool.get().await.unwrap();
+        assert!(first.get(b"Hello").await.unwrap().is_some());
+        second.noop().await.unwrap();
+    }
+    let stats = pool.stats();
+    assert_eq!((3, 2, 2, 2), (stats.checkouts, stats.connects, stats.open, stats.idle));
+}
+
+#[tokio::test]
+async fn checkouts_time_out() {
+    let pool = start(1);
+    let mut held = pool.get().await.unwrap();
+    match pool.get().await {
+        Err(ClientError::Io(ref err)) if err.kind() == io::ErrorKind::TimedOut => (),
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+    held.mark_broken();
+    drop(held);
+    pool.get().await.unwrap().noop().await.unwrap();
+    let stats = pool.stats();
+    assert_eq!((1, 1, 1, 2), (stats.waits, stats.timeouts, stats.broken, stats.connects));
+}
+
+#[tokio::test]
+async fn connectors_and_broken_connections() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || serve(listener, Arc::new(MemoryStore::new())));
+    let connects = Arc::new(AtomicUsize::new(0));
+    let counted = Arc::clone(&connects);
+    let pool = AsyncPool::with_connector(PoolConfig::default(), move || {
+        counted.fetch_add(1, Ordering::SeqCst);
+        let address = address.clone();
+        async move { Ok(AsyncClient::connect(address).await?) }
+    });
+
+    pool.run(|client| async move { client.set(b"Hello", b"World", 0, 0).await }).await.unwrap();
+    let err = pool.run(|client| async move { client.delete(b"missing").await }).await;
+    assert_eq!(Some(ResponseStatus::KeyNotFound), err.unwrap_err().status());
+    let broken: Result<(), _> = pool.run(|client| {
+        async move {
+            client.noop().await?;
+            Err(ClientError::Protocol("out of step".to_string()))
+        }
+    }).await;
+    assert!(broken.is_err());
+    pool.get().await.unwrap().noop().await.unwrap();
+    assert_eq!(2, connects.load(Ordering::SeqCst));
+    assert_eq!((1, 1, 1), (pool.stats().broken, pool.stats().open, pool.stats().idle));
+}
+
+#[tokio::test]
+async fn cancelled_health_checks_close_the_connection() {
+    // Accepts connections and never answers
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || {
+        let connections: Vec<_> = listener.incoming().collect();
+        drop(connections);
+    });
+    let config = PoolConfig {
+        io_timeout: Some(Duration::from_millis(50)),
+        ..PoolConfig::default()
+    };
+    let pool = AsyncPool::new(&address, config);
+    pool.get().await.unwrap();
+    assert_eq!(1, pool.stats().open);
+
+    let cancelled = tokio::time::timeout(Duration::from_millis(10), pool.get()).await;
+    assert!(cancelled.is_err());
+    assert_eq!((0, 0), (pool.stats().open, pool.stats().idle));
+
+    pool.get().await.unwrap();
+    match pool.get().await.map(|_| ()) {
+        Ok(()) => (),
+        Err(err) => panic!("{:?}", err),
+    }
+    assert_eq!(1, pool.stats().failed_health_checks);
+}
@@ -0,0 +1,304 @@
+extern crate memcache_protocol;
+use memcache_protocol::*;
+
+use std::collections::HashMap;
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+fn not_found() -> Reply {
+    Reply::Error(ResponseStatus::KeyNotFound, "Not found".to_string())
+}
+
+fn exists() -> Reply {
+    Reply::Error(ResponseStatus::KeyExists, "Data exists for key.".to_string())
+}
+
+fn concat<F>(items: &mut HashMap<Vec<u8>, (Vec<u8>, u32, u64)>, key: Vec<u8>, join: F, cas: u64)
+             -> Reply
+    where F: Fn(&[u8]) -> Vec<u8>
+{
+    match items.get_mut(&key) {
+        Some(item) => {
+            item.0 = join(&item.0);
+            item.2 = cas;
+            Reply::Stored { cas: cas }
+        }
+        None => Reply::Error(ResponseStatus::NotStored, "Not stored.".to_string()),
+    }
+}
+
+// A missing counter starts at initial, without applying the delta.
+fn counter<F>(items: &mut HashMap<Vec<u8>, (Vec<u8>, u32, u64)>, key: Vec<u8>, initial: u64,
+              apply: F)
+              -> Reply
+    where F: Fn(u64) -> u64
+{
+    let value = match items.get(&key) {
+        Some(item) => apply(String::from_utf8_lossy(&item.0).parse().unwrap()),
+        None => initial,
+    };
+    items.insert(key, (format!("{}", value).into_bytes(), 0, 0));
+    Reply::Counter { value: value, cas: 0 }
+}
+
+// Just enough of memcached to exercise the client, items are (value, flags,
+// cas) and expiration is ignored.
+fn answer(items: &mut HashMap<Vec<u8>, (Vec<u8>, u32, u64)>, next_cas: &mut u64,
+          command: Command)
+          -> Reply {
+    *next_cas += 1;
+    let cas = *next_cas;
+    match command {
+        Command::Get { key, .. } => {
+            match items.get(&key) {
+                Some(&(ref value, flags, cas)) => {
+                    Reply::Value {
+                        flags: flags,
+                        cas: cas,
+                        key: key.clone(),
+                        value: value.clone(),
+                    }
+                }
+                None => not_found(),
+            }
+        }
+        Command::Set { key, value, flags, .. } => {
+            items.insert(key, (value, flags, cas));
+            Reply::Stored { cas: cas }
+        }
+        Command::Add { ref key, .. } if items.contains_key(key) => exists(),
+        Command::Add { key, value, flags, .. } => {
+            items.insert(key, (value, flags, cas));
+            Reply::Stored { cas: cas }
+        }
+        Command::Replace { key, value, flags, .. } => {
+            match items.get_mut(&key) {
+                Some(item) => {
+                    *item = (value, flags, cas);
+                    Reply::Stored { cas: cas }
+                }
+                None => not_found(),
+            }
+        }
+        Command::Append { key, value, .. } => {
+            concat(items, key, |item| [item, &value[..]].concat(), cas)
+        }
+        Command::Prepend { key, value, .. } => {
+            concat(items, key, |item| [&value[..], item].concat(), cas)
+        }
+        Command::Delete { key, .. } => {
+            match items.remove(&key) {
+                Some(_) => Reply::Deleted { cas: 0 },
+                None => not_found(),
+            }
+        }
+        Command::Incr { key, delta, initial, .. } => counter(items, key, initial, |v| v + delta),
+        Command::Decr { key, delta, initial, .. } => {
+            counter(items, key, initial, |v| v.saturating_sub(delta))
+        }
+        Command::Flush { .. } => {
+            items.clear();
+            Reply::Flushed
+        }
+        Command::Version => Reply::Version("1.6.21".to_string()),
+        Command::Noop => Reply::Noop,
+        Command::Quit { .. } => Reply::Quit,
+        _ => Reply::Error(ResponseStatus::UnknownCommand, "Unknown command".to_string()),
+    }
+}
+
+// Accepts a single connection and answers until it is closed or told to quit.
+fn mock_server() -> (TcpStream, thread::JoinHandle<Vec<Command>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut items = HashMap::new();
+        let mut next_cas = 0;
+        let mut received = Vec::new();
+        let mut buf = Vec::new();
+        while read_packet(&mut stream, &mut buf).is_ok() {
+            let (_, request) = packet(&buf).unwrap();
+            let opaque = match request.header {
+                HeaderType::Request(ref h) => h.opaque,
+                _ => panic!(),
+            };
+            let command = Command::from_packet(&request).unwrap();
+            let opcode = command.opcode();
+            if let Command::Stat { .. } = command {
+                for &(key, value) in [("pid", "1234"), ("uptime", "42"), ("", "")].iter() {
+                    Reply::Stat { key: key.as_bytes().to_vec(), value: value.as_bytes().to_vec() }
+                        .write_to(opcode, opaque, &mut stream)
+                        .unwrap();
+                }
+            } else {
+                let reply = answer(&mut items, &mut next_cas, command.clone());
+                // Quiet commands only answer misses for the gets and failures
+                // for the rest
+                let silent = match reply {
+                    Reply::Error(ResponseStatus::KeyNotFound, _) => {
+                        opcode == Opcode::GetQ || opcode == Opcode::GetKQ
+                    }
+                    Reply::Error(..) | Reply::Value { .. } => false,
+                    _ => true,
+                };
+                if !(opcode.is_quiet() && silent) {
+                    reply.write_to(opcode, opaque, &mut stream).unwrap();
+                }
+            }
+            received.push(command);
+            if opcode == Opcode::Quit {
+                break;
+            }
+        }
+        received
+    });
+    (TcpStream::connect(address).unwrap(), server)
+}
+
+#[test]
+fn storage() {
+    let (stream, server) = mock_server();
+    let mut client = Client::new(stream);
+    assert_eq!(None, client.get(b"Hello").unwrap());
+    let cas = client.set(b"Hello", b"World", 0xdeadbeef, 0).unwrap();
+    assert_eq!(Some(Item { value: b"World".to_vec(), flags: 0xdeadbeef, cas: cas }),
+               client.get(b"Hello").unwrap());
+
+    let err = client.add(b"Hello", b"Again", 0, 0).unwrap_err();
+    assert_eq!(Some(ResponseStatus::KeyExists), err.status());
+    assert_eq!(Some(ResponseStatus::KeyNotFound),
+               client.replace(b"Missing", b"", 0, 0).unwrap_err().status());
+    client.replace(b"Hello", b"there", 0, 0).unwrap();
+    client.append(b"Hello", b"!").unwrap();
+    client.prepend(b"Hello", b"Hello ").unwrap();
+    assert_eq!(b"Hello there!".to_vec(), client.get(b"Hello").unwrap().unwrap().value);
+
+    client.delete(b"Hello").unwrap();
+    assert_eq!(Some(ResponseStatus::KeyNotFound),
+               client.delete(b"Hello").unwrap_err().status());
+    client.add(b"Hello", b"World", 0, 0).unwrap();
+    client.flush(None).unwrap();
+    assert_eq!(None, client.get(b"Hello").unwrap());
+    client.quit().unwrap();
+
+    let received = server.join().unwrap();
+    assert_eq!(Command::Quit { quiet: false }, received[received.len() - 1]);
+}
+
+#[test]
+fn get_multi() {
+    let (stream, server) = mock_server();
+    let mut client = Client::new(stream);
+    let keys: Vec<String> = (0..600).map(|i| format!("key{}", i)).collect();
+    for key in keys.iter().step_by(3) {
+        client.set(key.as_bytes(), key.to_uppercase().as_bytes(), 0, 0).unwrap();
+    }
+    let items = client.get_multi(&keys).unwrap();
+    assert_eq!(200, items.len());
+    for (i, key) in keys.iter().enumerate() {
+        match items.get(key.as_bytes()) {
+            Some(item) => assert_eq!(key.to_uppercase().into_bytes(), item.value),
+            None => assert!(i % 3 != 0),
+        }
+    }
+    assert!(client.get_multi(Vec::<Vec<u8>>::new()).unwrap().is_empty());
+    client.quit().unwrap();
+
+    // Sent in chunks, each ended by a noop
+    let received = server.join().unwrap();
+    let noops = received.iter().filter(|&command| command == &Command::Noop).count();
+    assert_eq!(3, noops);
+}
+
+#[test]
+fn batch() {
+    let (stream, server) = mock_server();
+    let mut client = Client::new(stream);
+    let items: Vec<(String, String)> =
+        (0..300).map(|i| (format!("key{}", i), format!("value{}", i))).collect();
+    assert_eq!(Vec::<BatchFailure>::new(), client.set_multi(items, 0, 0).unwrap());
+    assert_eq!(b"value299".to_vec(), client.get(b"key299").unwrap().unwrap().value);
+
+    let add = |key: &str| {
+        Command::Add {
+            key: key.as_bytes().to_vec(),
+            value: Vec::new(),
+            flags: 0,
+            expiration: 0,
+            quiet: false,
+        }
+    };
+    let commands = vec![
+        add("key1"),
+        add("new"),
+        Command::Incr {
+            key: b"counter".to_vec(),
+            delta: 1,
+            initial: 0,
+            expiration: 0,
+            quiet: false,
+        },
+        Command::Version,
+        Command::Delete { key: b"missing".to_vec(), cas: 0, quiet: false },
+    ];
+    let failures = client.batch(commands).unwrap();
+    assert_eq!(vec![0, 4], failures.iter().map(|failure| failure.index).collect::<Vec<_>>());
+    assert_eq!(ResponseStatus::KeyExists, failures[0].status);
+    assert_eq!(ResponseStatus::KeyNotFound, failures[1].status);
+    assert!(client.get(b"new").unwrap().is_some());
+
+    let keys = vec!["key0", "missing", "key299"];
+    let failures = client.delete_multi(&keys).unwrap();
+    assert_eq!(1, failures.len());
+    assert_eq!(1, failures[0].index);
+    assert_eq!(None, client.get(b"key0").unwrap());
+    client.quit().unwrap();
+
+    let received = server.join().unwrap();
+    assert!(received.iter().all(|command| match command {
+        &Command::Set { quiet, .. } | &Command::Add { quiet, .. } => quiet,
+        _ => true,
+    }));
+}
+
+#[test]
+fn counters() {
+    let (stream, _) = mock_server();
+    let mut client = Client::new(stream);
+    assert_eq!(10, client.incr(b"counter", 5, 10, 0).unwrap());
+    assert_eq!(15, client.incr(b"counter", 5, 10, 0).unwrap());
+    assert_eq!(3, client.decr(b"counter", 12, 0, 0).unwrap());
+    assert_eq!(0, client.decr(b"counter", 12, 0, 0).unwrap());
+}
+
+#[test]
+fn server_information() {
+    let (stream, _) = mock_server();
+    let mut client = Client::new(stream);
+    client.noop().unwrap();
+    assert_eq!("1.6.21", client.version().unwrap());
+    let stats = client.stats(b"").unwrap();
+    assert_eq!(2, stats.len());
+    assert_eq!("1234", stats["pid"]);
+    assert_eq!("42", stats["uptime"]);
+    // The connection is still usable after the stats
+    client.noop().unwrap();
+}
+
+#[test]
+fn mismatched_response() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut buf = Vec::new();
+        read_packet(&mut stream, &mut buf).unwrap();
+        Reply::Noop.write_to(Opcode::Noop, 0xffff, &mut stream).unwrap();
+    });
+    let mut client = Client::connect(address).unwrap();
+    match client.noop() {
+        Err(ClientError::Protocol(_)) => (),
+        other => panic!("{:?}", other),
+    }
+}
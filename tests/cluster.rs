@@ -0,0 +1,298 @@
+extern crate memcache_protocol;
+use memcache_protocol::*;
+
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn start() -> Node {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let store = MemoryStore::new().with_max_value_length(10);
+    thread::spawn(move || serve(listener, Arc::new(store)));
+    Node::new("127.0.0.1", port)
+}
+
+// A server that can be taken down and brought back up. While it's down its
+// connections are shut down, and new ones as soon as they're accepted.
+struct Server {
+    down: AtomicBool,
+    connections: Mutex<Vec<TcpStream>>,
+}
+
+impl Server {
+    fn kill(&self) {
+        self.down.store(true, Ordering::SeqCst);
+        for stream in self.connections.lock().unwrap().drain(..) {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+
+    fn revive(&self) {
+        self.down.store(false, Ordering::SeqCst);
+    }
+}
+
+fn start_server() -> (Node, Arc<Server>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = Arc::new(Server {
+        down: AtomicBool::new(false),
+        connections: Mutex::new(Vec::new()),
+    });
+    let switch = Arc::clone(&server);
+    thread::spawn(move || {
+        let store = Arc::new(MemoryStore::new());
+        for stream in listener.incoming() {
+            let stream = stream.unwrap();
+            if switch.down.load(Ordering::SeqCst) {
+                let _ = stream.shutdown(Shutdown::Both);
+                continue;
+            }
+            switch.connections.lock().unwrap().push(stream.try_clone().unwrap());
+            let store = Arc::clone(&store);
+            thread::spawn(move || serve_connection(&store, stream.try_clone().unwrap(), stream));
+        }
+    });
+    (Node::new("127.0.0.1", port), server)
+}
+
+#[test]
+fn keys_are_hashed_with_md5() {
+    // The first four bytes of the RFC 1321 digests, little endian
+    assert_eq!(0xd98c1dd4, Ketama::hash(b""));
+    assert_eq!(0x98500190, Ketama::hash(b"abc"));
+}
+
+#[test]
+fn points_are_shared_by_weight() {
+    let nodes = vec![Node::new("10.0.1.1", 11211),
+                     Node::new("10.0.1.2", 11211),
+                     Node::new("10.0.1.3", 11211)];
+    assert_eq!(480, Ketama::new(&nodes).points());
+
+    let weighted = vec![Node::new("10.0.1.1", 11211),
+                        Node::new("10.0.1.2", 11211).with_weight(2),
+                        Node::new("10.0.1.3", 11211).with_weight(0)];
+    assert_eq!(480, Ketama::new(&weighted).points());
+    assert_eq!(None, Ketama::new(&[]).node(b"key"));
+}
+
+#[test]
+fn points_are_named_like_libmemcached() {
+    // The default port is left out of the name
+    let nodes = vec![Node::new("10.0.1.1", 11211), Node::new("10.0.1.2", 11212)];
+    let ketama = Ketama::new(&nodes);
+    for i in 0..40 {
+        let first = format!("10.0.1.1-{}", i);
+        let second = format!("10.0.1.2:11212-{}", i);
+        assert_eq!(Some(0), ketama.node_for_hash(Ketama::hash(first.as_bytes())));
+        assert_eq!(Some(1), ketama.node_for_hash(Ketama::hash(second.as_bytes())));
+    }
+}
+
+#[test]
+fn keys_go_where_libmemcached_puts_them() {
+    // The servers of libmemcached's weighted ketama tests. The expected nodes
+    // come from a separate port of its update_continuum and dispatch_host, not
+    // from this crate
+    let weights = [600, 300, 200, 350, 1000, 800, 950, 100];
+    let nodes: Vec<Node> = weights.iter()
+        .enumerate()
+        .map(|(i, &weight)| Node::new(&format!("10.0.1.{}", i + 1), 11211).with_weight(weight))
+        .collect();
+    let ketama = Ketama::new(&nodes);
+    assert_eq!(1264, ketama.points());
+    let expected = [("apple", 5), ("beat", 5), ("carrot", 5), ("daikon", 1), ("eggplant", 5),
+                    ("flower", 4), ("green", 3), ("hide", 5), ("ick", 4), ("jack", 7),
+                    ("kick", 5), ("lime", 6), ("mushrooms", 5), ("nectarine", 6),
+                    ("orange", 7), ("peach", 5), ("quant", 1), ("ripen", 7),
+                    ("strawberry", 7), ("tang", 7), ("up", 6), ("volumne", 1), ("when", 7),
+                    ("yellow", 5), ("zip", 4)];
+    for &(key, server) in expected.iter() {
+        assert_eq!(Some(server - 1), ketama.node(key.as_bytes()), "{}", key);
+    }
+    assert_eq!(3195025439, Ketama::hash(b"apple"));
+    assert_eq!(121710495, Ketama::hash(b"green"));
+}
+
+#[test]
+fn removing_a_node_only_moves_its_keys() {
+    let nodes: Vec<Node> = (1..6).map(|i| Node::new(&format!("10.0.1.{}", i), 11211)).collect();
+    let before = Ketama::new(&nodes);
+    let after = Ketama::new(&nodes[1..]);
+    let mut moved = 0;
+    for i in 0..1000 {
+        let key = format!("key:{}", i);
+        let node = before.node(key.as_bytes()).unwrap();
+        if node == 0 {
+            moved += 1;
+        } else {
+            assert_eq!(Some(node - 1), after.node(key.as_bytes()));
+        }
+    }
+    assert!(moved > 100 && moved < 300, "{} keys moved", moved);
+}
+
+#[test]
+fn cluster_routes_keys_to_their_node() {
+    let nodes = vec![start(), start(), start()];
+    let mut cluster = Cluster::connect(nodes).unwrap();
+    let keys: Vec<String> = (0..30).map(|i| format!("key:{}", i)).collect();
+    let mut items: Vec<(&str, &str)> = keys.iter().map(|key| (&key[..], "value")).collect();
+    items[7].1 = "much too large";
+    let failures = cluster.set_multi(items, 0, 0).unwrap();
+    assert_eq!(1, failures.len());
+    assert_eq!((7, ResponseStatus::ValueTooLarge), (failures[0].index, failures[0].status));
+
+    for key in &keys[8..] {
+        let node = cluster.node(key.as_bytes()).unwrap();
+        let mut direct = Client::connect((&node.host[..], node.port)).unwrap();
+        assert!(direct.get(key.as_bytes()).unwrap().is_some());
+    }
+
+    let found = cluster.get_multi(&keys).unwrap();
+    assert_eq!(29, found.len());
+    assert_eq!(1, cluster.incr(b"counter", 1, 1, 0).unwrap());
+    assert_eq!(2, cluster.incr(b"counter", 1, 1, 0).unwrap());
+    cluster.flush(None).unwrap();
+    assert_eq!(None, cluster.get(keys[0].as_bytes()).unwrap());
+    assert!(Cluster::new(Vec::new(), Vec::<Client>::new()).get(b"key").is_err());
+}
+
+#[test]
+fn other_distributors() {
+    let nodes = vec![start(), start()];
+    let modula = Modula::new(&[], KeyHash::Crc32);
+    let mut cluster = Cluster::connect_with_distributor(nodes.clone(), modula).unwrap();
+    for key in &["apple", "beat", "carrot", "daikon"] {
+        let node = &nodes[KeyHash::Crc32.hash(key.as_bytes()) as usize % 2];
+        assert_eq!(Some(node), cluster.node(key.as_bytes()));
+        cluster.set(key.as_bytes(), b"value", 0, 0).unwrap();
+        let mut direct = Client::connect((&node.host[..], node.port)).unwrap();
+        assert!(direct.get(key.as_bytes()).unwrap().is_some());
+    }
+}
+
+#[test]
+fn failing_nodes_are_ejected_and_probed() {
+    let (node, server) = start_server();
+    let other = start();
+    let failover = FailoverConfig {
+        failure_limit: Some(2),
+        retry_timeout: Duration::from_millis(100),
+        max_retry_timeout: Duration::from_secs(1),
+        ..FailoverConfig::default()
+    };
+    let mut cluster = Cluster::connect(vec![node.clone(), other.clone()])
+        .unwrap()
+        .with_failover(failover);
+    let key = (0..).map(|i| format!("key:{}", i))
+        .find(|key| cluster.node(key.as_bytes()) == Some(&node))
+        .unwrap();
+    let key = key.as_bytes();
+    cluster.set(key, b"value", 0, 0).unwrap();
+
+    server.kill();
+    assert!(cluster.get(key).unwrap_err().is_connection_error());
+    assert!(cluster.ejected().is_empty());
+    assert!(cluster.get(key).is_err());
+    assert_eq!(vec![&node], cluster.ejected());
+    assert_eq!(Some(&other), cluster.node(key));
+    cluster.set(key, b"moved", 0, 0).unwrap();
+
+    // The first probe fails, the next one is due twice as long after it
+    thread::sleep(Duration::from_millis(120));
+    assert_eq!(b"moved", &cluster.get(key).unwrap().unwrap().value[..]);
+    server.revive();
+    thread::sleep(Duration::from_millis(120));
+    assert_eq!(b"moved", &cluster.get(key).unwrap().unwrap().value[..]);
+    assert_eq!(vec![&node], cluster.ejected());
+    thread::sleep(Duration::from_millis(120));
+    assert_eq!(b"value", &cluster.get(key).unwrap().unwrap().value[..]);
+    assert!(cluster.ejected().is_empty());
+}
+
+#[test]
+fn hung_nodes_are_ejected() {
+    // Accepts connections and never answers
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let hung = Node::new("127.0.0.1", listener.local_addr().unwrap().port());
+    thread::spawn(move || {
+        let connections: Vec<_> = listener.incoming().collect();
+        drop(connections);
+    });
+    let other = start();
+    let failover = FailoverConfig {
+        failure_limit: Some(2),
+        retry_timeout: Duration::from_millis(100),
+        io_timeout: Some(Duration::from_millis(50)),
+        ..FailoverConfig::default()
+    };
+    let mut cluster = Cluster::connect(vec![hung.clone(), other.clone()])
+        .unwrap()
+        .with_failover(failover);
+    let key = (0..).map(|i| format!("key:{}", i))
+        .find(|key| cluster.node(key.as_bytes()) == Some(&hung))
+        .unwrap();
+    let key = key.as_bytes();
+
+    let started = Instant::now();
+    assert!(cluster.get(key).unwrap_err().is_connection_error());
+    assert!(cluster.get(key).unwrap_err().is_connection_error());
+    assert_eq!(vec![&hung], cluster.ejected());
+    assert_eq!(None, cluster.get(key).unwrap());
+
+    // Probing it times out as well
+    thread::sleep(Duration::from_millis(120));
+    assert_eq!(None, cluster.get(key).unwrap());
+    assert_eq!(vec![&hung], cluster.ejected());
+    assert!(started.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn nodes_down_at_the_start_are_connected_later() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    drop(listener);
+    let down = Node::new("127.0.0.1", port);
+    let other = start();
+    let mut cluster = Cluster::connect(vec![down.clone(), other.clone()]).unwrap();
+    assert!(cluster.ejected().is_empty());
+    let key = (0..).map(|i| format!("key:{}", i))
+        .find(|key| cluster.node(key.as_bytes()) == Some(&down))
+        .unwrap();
+    let key = key.as_bytes();
+
+    let listener = TcpListener::bind(("127.0.0.1", port)).unwrap();
+    thread::spawn(move || serve(listener, Arc::new(MemoryStore::new())));
+    cluster.set(key, b"value", 0, 0).unwrap();
+    assert_eq!(Some(&down), cluster.node(key));
+
+    // Failing to connect counts towards ejecting it
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let down = Node::new("127.0.0.1", listener.local_addr().unwrap().port());
+    drop(listener);
+    let mut cluster = Cluster::connect(vec![down.clone(), other.clone()]).unwrap();
+    let key = (0..).map(|i| format!("key:{}", i))
+        .find(|key| cluster.node(key.as_bytes()) == Some(&down))
+        .unwrap();
+    let key = key.as_bytes();
+    assert!(cluster.get(key).unwrap_err().is_connection_error());
+    assert_eq!(vec![&down], cluster.ejected());
+    assert_eq!(None, cluster.get(key).unwrap());
+}
+
+#[test]
+fn nodes_without_a_connector_are_not_ejected() {
+    let (node, server) = start_server();
+    let client = Client::connect(("127.0.0.1", node.port)).unwrap();
+    let mut cluster = Cluster::new(vec![node], vec![client]);
+    server.kill();
+    for _ in 0..3 {
+        assert!(cluster.get(b"key").unwrap_err().is_connection_error());
+    }
+    assert!(cluster.ejected().is_empty());
+}
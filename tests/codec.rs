@@ -0,0 +1,88 @@
+#![cfg(feature = "tokio")]
+extern crate futures;
+extern crate memcache_protocol;
+extern crate tokio;
+extern crate tokio_util;
+use memcache_protocol::*;
+
+use futures::{SinkExt, StreamExt};
+use tokio_util::codec::{Decoder, Framed};
+
+fn get(key: &str) -> Command {
+    Command::Get { key: key.as_bytes().to_vec(), quiet: false, return_key: false }
+}
+
+#[tokio::test]
+async fn commands_over_a_duplex_stream() {
+    let (client, server) = tokio::io::duplex(64);
+    let mut client = Framed::new(client, MemcacheCodec::new());
+    let mut server = Framed::new(server, MemcacheCodec::new());
+
+    // Larger than the duplex buffer, so the packet arrives in pieces
+    let set = Command::Set {
+        key: b"Hello".to_vec(),
+        value: vec![b'x'; 1000],
+        flags: 0xdeadbeef,
+        expiration: 3600,
+        cas: 0,
+        quiet: false,
+    };
+    let sent = vec![(1, get("Hello")), (2, set), (3, Command::Noop)];
+    let writer = async {
+        for request in sent.iter().cloned() {
+            client.send(request).await.unwrap();
+        }
+        client
+    };
+    let reader = async {
+        let mut received = Vec::new();
+        while received.len() < 3 {
+            let request = server.next().await.unwrap().unwrap();
+            let opaque = match request.header {
+                HeaderType::Request(ref h) => h.opaque,
+                _ => panic!(),
+            };
+            received.push((opaque, Command::from_packet(&request.packet()).unwrap()));
+        }
+        (server, received)
+    };
+    let (mut client, (mut server, received)) = futures::join!(writer, reader);
+    assert_eq!(sent, received);
+
+    let reply = Reply::Value {
+        flags: 0,
+        cas: 1,
+        key: Vec::new(),
+        value: b"World".to_vec(),
+    };
+    let mut response = Vec::new();
+    reply.write_to(Opcode::Get, 1, &mut response).unwrap();
+    let (_, response) = packet(&response).unwrap();
+    server.send(response.clone()).await.unwrap();
+    let received = client.next().await.unwrap().unwrap();
+    assert_eq!(response, received.packet());
+    assert_eq!(reply, Reply::from_packet(&received.packet()).unwrap());
+}
+
+#[test]
+fn decode_splits_the_buffer() {
+    let mut src = bytes::BytesMut::new();
+    src.extend_from_slice(&get("Hello").encode(1).unwrap());
+    src.extend_from_slice(&get("World").encode(2).unwrap()[..20]);
+    let mut codec = MemcacheCodec::new();
+    let request = codec.decode(&mut src).unwrap().unwrap();
+    assert_eq!(&b"Hello"[..], &request.key[..]);
+    assert_eq!(20, src.len());
+    assert!(codec.decode(&mut src).unwrap().is_none());
+    src.extend_from_slice(&get("World").encode(2).unwrap()[20..]);
+    assert_eq!(&b"World"[..], &codec.decode(&mut src).unwrap().unwrap().key[..]);
+    assert!(src.is_empty());
+}
+
+#[test]
+fn invalid_packets_are_errors() {
+    let mut src = bytes::BytesMut::from(&[0x82u8, 0x00][..]);
+    let err = MemcacheCodec::new().decode(&mut src).unwrap_err();
+    assert_eq!(std::io::ErrorKind::InvalidData, err.kind());
+    assert_eq!("invalid magic 0x82 at byte 0", err.to_string());
+}
@@ -0,0 +1,204 @@
+extern crate memcache_protocol;
+use memcache_protocol::*;
+
+fn round_trip_command(command: Command) {
+    let bytes = command.encode(0x1234).unwrap();
+    let (remaining, parsed) = packet(&bytes).unwrap();
+    assert_eq!(&b""[..], remaining);
+    match parsed.header {
+        HeaderType::Request(ref h) => {
+            assert_eq!(command.opcode(), h.opcode);
+            assert_eq!(0x1234, h.opaque);
+        }
+        _ => panic!(),
+    }
+    assert_eq!(Ok(command), Command::from_packet(&parsed));
+}
+
+fn round_trip_reply(opcode: Opcode, reply: Reply) {
+    let bytes = reply.encode(opcode, 0x4321).unwrap();
+    let (remaining, parsed) = packet(&bytes).unwrap();
+    assert_eq!(&b""[..], remaining);
+    match parsed.header {
+        HeaderType::Response(ref h) => {
+            assert_eq!(opcode, h.opcode);
+            assert_eq!(0x4321, h.opaque);
+        }
+        _ => panic!(),
+    }
+    assert_eq!(Ok(reply), Reply::from_packet(&parsed));
+}
+
+#[test]
+fn commands_round_trip() {
+    let key = b"Hello".to_vec();
+    let value = b"World".to_vec();
+    for &quiet in &[false, true] {
+        for &return_key in &[false, true] {
+            round_trip_command(Command::Get {
+                key: key.clone(),
+                quiet: quiet,
+                return_key: return_key,
+            });
+        }
+        round_trip_command(Command::Set {
+            key: key.clone(),
+            value: value.clone(),
+            flags: 0xdeadbeef,
+            expiration: 3600,
+            cas: 42,
+            quiet: quiet,
+        });
+        round_trip_command(Command::Add {
+            key: key.clone(),
+            value: value.clone(),
+            flags: 1,
+            expiration: 0,
+            quiet: quiet,
+        });
+        round_trip_command(Command::Replace {
+            key: key.clone(),
+            value: value.clone(),
+            flags: 2,
+            expiration: 60,
+            cas: 7,
+            quiet: quiet,
+        });
+        round_trip_command(Command::Delete { key: key.clone(), cas: 3, quiet: quiet });
+        round_trip_command(Command::Incr {
+            key: key.clone(),
+            delta: 1,
+            initial: 0,
+            expiration: 3600,
+            quiet: quiet,
+        });
+        round_trip_command(Command::Decr {
+            key: key.clone(),
+            delta: 5,
+            initial: 10,
+            expiration: 0xffffffff,
+            quiet: quiet,
+        });
+        round_trip_command(Command::Quit { quiet: quiet });
+        round_trip_command(Command::Flush { expiration: None, quiet: quiet });
+        round_trip_command(Command::Flush { expiration: Some(10), quiet: quiet });
+        round_trip_command(Command::Append {
+            key: key.clone(),
+            value: value.clone(),
+            cas: 0,
+            quiet: quiet,
+        });
+        round_trip_command(Command::Prepend {
+            key: key.clone(),
+            value: value.clone(),
+            cas: 9,
+            quiet: quiet,
+        });
+    }
+    round_trip_command(Command::Noop);
+    round_trip_command(Command::Version);
+    round_trip_command(Command::Stat { key: Vec::new() });
+    round_trip_command(Command::Stat { key: b"slabs".to_vec() });
+    round_trip_command(Command::Verbosity { level: 1 });
+    round_trip_command(Command::Touch { key: b"Hello".to_vec(), expiration: 3600 });
+    round_trip_command(Command::GetAndTouch {
+        key: b"Hello".to_vec(),
+        expiration: 3600,
+        quiet: false,
+    });
+    round_trip_command(Command::GetAndTouch {
+        key: b"Hello".to_vec(),
+        expiration: 0,
+        quiet: true,
+    });
+    round_trip_command(Command::SaslListMechanisms);
+    round_trip_command(Command::SaslAuth {
+        mechanism: b"PLAIN".to_vec(),
+        data: b"\0user\0password".to_vec(),
+    });
+    round_trip_command(Command::SaslStep {
+        mechanism: b"CRAM-MD5".to_vec(),
+        data: b"user 0123456789abcdef".to_vec(),
+    });
+}
+
+#[test]
+fn replies_round_trip() {
+    round_trip_reply(Opcode::GetK,
+                     Reply::Value {
+                         flags: 0xdeadbeef,
+                         cas: 1,
+                         key: b"Hello".to_vec(),
+                         value: b"World".to_vec(),
+                     });
+    round_trip_reply(Opcode::Get,
+                     Reply::Value {
+                         flags: 0,
+                         cas: 2,
+                         key: Vec::new(),
+                         value: b"World".to_vec(),
+                     });
+    round_trip_reply(Opcode::Set, Reply::Stored { cas: 3 });
+    round_trip_reply(Opcode::PrependQ, Reply::Stored { cas: 4 });
+    round_trip_reply(Opcode::Delete, Reply::Deleted { cas: 7 });
+    round_trip_reply(Opcode::Touch, Reply::Touched { cas: 5 });
+    round_trip_reply(Opcode::Verbosity, Reply::Verbosity);
+    round_trip_reply(Opcode::GetAndTouchQ,
+                     Reply::Value {
+                         flags: 3,
+                         cas: 6,
+                         key: Vec::new(),
+                         value: b"World".to_vec(),
+                     });
+    round_trip_reply(Opcode::Increment, Reply::Counter { value: 0xffffffffffffffff, cas: 8 });
+    round_trip_reply(Opcode::Quit, Reply::Quit);
+    round_trip_reply(Opcode::Flush, Reply::Flushed);
+    round_trip_reply(Opcode::Noop, Reply::Noop);
+    round_trip_reply(Opcode::Version, Reply::Version("1.3.1".to_string()));
+    round_trip_reply(Opcode::Stat,
+                     Reply::Stat {
+                         key: b"pid".to_vec(),
+                         value: b"3078".to_vec(),
+                     });
+    round_trip_reply(Opcode::Stat, Reply::Stat { key: Vec::new(), value: Vec::new() });
+    round_trip_reply(Opcode::Get,
+                     Reply::Error(ResponseStatus::KeyNotFound, "Not found".to_string()));
+    round_trip_reply(Opcode::SaslListMechanisms, Reply::Mechanisms("CRAM-MD5 PLAIN".to_string()));
+    round_trip_reply(Opcode::SaslAuth, Reply::Authenticated(b"Authenticated".to_vec()));
+}
+
+#[test]
+fn increment_response_reply() {
+    let contents: &[u8] = &[0x81, 0x05, 0x00, 0x00,
+                            0x00, 0x00, 0x00, 0x00,
+                            0x00, 0x00, 0x00, 0x08,
+                            0x00, 0x00, 0x00, 0x00,
+                            0x00, 0x00, 0x00, 0x00,
+                            0x00, 0x00, 0x00, 0x05,
+                            0x00, 0x00, 0x00, 0x00,
+                            0x00, 0x00, 0x00, 0x00];
+    let (_, packet) = packet(contents).unwrap();
+    assert_eq!(Ok(Reply::Counter { value: 0, cas: 5 }), Reply::from_packet(&packet));
+}
+
+#[test]
+fn invalid_counter_body() {
+    let bytes = Reply::Version("1".to_string()).encode(Opcode::Increment, 0).unwrap();
+    let (_, packet) = packet(&bytes).unwrap();
+    assert_eq!(Err(CommandError::InvalidBody {
+                   opcode: Opcode::Increment,
+                   expected: 8,
+                   actual: 1,
+               }),
+               Reply::from_packet(&packet));
+}
+
+#[test]
+fn wrong_direction() {
+    let bytes = Command::Noop.encode(0).unwrap();
+    let (_, request) = packet(&bytes).unwrap();
+    assert_eq!(Err(CommandError::NotAResponse), Reply::from_packet(&request));
+    let bytes = Reply::Noop.encode(Opcode::Noop, 0).unwrap();
+    let (_, response) = packet(&bytes).unwrap();
+    assert_eq!(Err(CommandError::NotARequest), Command::from_packet(&response));
+}
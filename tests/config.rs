@@ -0,0 +1,94 @@
+extern crate memcache_protocol;
+use memcache_protocol::*;
+
+use std::io;
+
+fn get_request(key_length: usize) -> Vec<u8> {
+    let key = vec![b'k'; key_length];
+    Command::Get { key: key, quiet: false, return_key: false }.encode(0).unwrap()
+}
+
+#[test]
+fn memcached_key_limit() {
+    let config = ParserConfig::default();
+    assert!(packet_with_config(&get_request(250), &config).is_ok());
+    let err = ParseError::TooLarge { field: LengthField::Key, length: 251, limit: 250 };
+    assert_eq!(Err(err), packet_with_config(&get_request(251), &config).map(|_| ()));
+    // Only the header is needed to reject it
+    assert_eq!(Err(err),
+               packet_with_config(&get_request(251)[..HEADER_LENGTH], &config).map(|_| ()));
+    assert_eq!("key length 251 at byte 2 exceeds the limit of 250", err.to_string());
+    assert!(packet(&get_request(251)).is_ok());
+}
+
+#[test]
+fn extras_limit() {
+    let config = ParserConfig { max_extras_length: 4, ..ParserConfig::default() };
+    let set = Command::Set {
+        key: b"Hello".to_vec(),
+        value: b"World".to_vec(),
+        flags: 0,
+        expiration: 0,
+        cas: 0,
+        quiet: false,
+    };
+    assert_eq!(Err(ParseError::TooLarge { field: LengthField::Extras, length: 8, limit: 4 }),
+               packet_with_config(&set.encode(0).unwrap(), &config).map(|_| ()));
+}
+
+#[test]
+fn body_limit() {
+    let config = ParserConfig { max_body_length: 1024, ..ParserConfig::default() };
+    let mut header = RequestHeader::new(Opcode::Set);
+    header.key_length = 5;
+    header.extras_length = 8;
+    header.body_length = 0xffffffff;
+    let mut contents = Vec::new();
+    header.write_to(&mut contents).unwrap();
+    let err = ParseError::TooLarge { field: LengthField::Body, length: 0xffffffff, limit: 1024 };
+    assert_eq!(Err(err), packet_with_config(&contents, &config).map(|_| ()));
+    assert_eq!("total body length 4294967295 at byte 8 exceeds the limit of 1024",
+               err.to_string());
+
+    let mut buf = Vec::new();
+    let read = read_packet_with_config(&mut &contents[..], &mut buf, &config).unwrap_err();
+    assert_eq!(io::ErrorKind::InvalidData, read.kind());
+}
+
+#[test]
+fn read_packet_uses_the_default_limits() {
+    let mut header = RequestHeader::new(Opcode::Set);
+    header.key_length = 5;
+    header.extras_length = 8;
+    header.body_length = 0xffffffff;
+    let mut contents = Vec::new();
+    header.write_to(&mut contents).unwrap();
+    // Only the header is there, so reading on would fail with UnexpectedEof
+    let mut buf = Vec::new();
+    let err = read_packet(&mut &contents[..], &mut buf).unwrap_err();
+    assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    assert!(buf.capacity() < 1024);
+}
+
+#[test]
+fn default_limits() {
+    let config = ParserConfig::default();
+    assert_eq!(DEFAULT_MAX_KEY_LENGTH, config.max_key_length);
+    assert_eq!(DEFAULT_MAX_EXTRAS_LENGTH, config.max_extras_length);
+    assert_eq!(DEFAULT_MAX_VALUE_LENGTH + 250 + 20, config.max_body_length);
+    assert!(!config.allow_unknown_opcodes);
+}
+
+#[test]
+fn unknown_opcodes() {
+    let mut contents = get_request(5);
+    contents[1] = 0xfe;
+    let config = ParserConfig { allow_unknown_opcodes: true, ..ParserConfig::default() };
+    let (_, request) = packet_with_config(&contents, &config).unwrap();
+    match request.header {
+        HeaderType::Request(h) => assert_eq!(Opcode::Other(0xfe), h.opcode),
+        _ => panic!(),
+    }
+    assert_eq!(Err(ParseError::UnknownOpcode(0xfe)),
+               packet_with_config(&contents, &ParserConfig::default()).map(|_| ()));
+}
@@ -0,0 +1,82 @@
+extern crate memcache_protocol;
+use memcache_protocol::*;
+
+fn get(key: &[u8], opaque: u32) -> Vec<u8> {
+    Command::Get { key: key.to_vec(), quiet: false, return_key: false }.encode(opaque).unwrap()
+}
+
+#[test]
+fn several_packets_in_one_chunk() {
+    let mut contents = get(b"Hello", 1);
+    contents.extend(get(b"World", 2));
+    contents.extend(&get(b"Again", 3)[..10]);
+    let mut decoder = Decoder::new();
+    decoder.feed(&contents);
+    for &(key, opaque) in [(&b"Hello"[..], 1), (&b"World"[..], 2)].iter() {
+        let request = decoder.decode().unwrap().unwrap();
+        assert_eq!(key, request.key);
+        match request.header {
+            HeaderType::Request(h) => assert_eq!(opaque, h.opaque),
+            _ => panic!(),
+        }
+    }
+    assert_eq!(Ok(None), decoder.decode());
+    assert_eq!(14, decoder.needed());
+    decoder.feed(&get(b"Again", 3)[10..]);
+    assert_eq!(&b"Again"[..], decoder.decode().unwrap().unwrap().key);
+    assert_eq!(Ok(None), decoder.decode());
+    assert_eq!(0, decoder.buffered());
+}
+
+#[test]
+fn header_is_kept_while_the_body_arrives() {
+    let contents = get(b"Hello", 7);
+    let mut decoder = Decoder::new();
+    decoder.feed(&contents[..HEADER_LENGTH]);
+    assert_eq!(None, decoder.header());
+    assert_eq!(Ok(None), decoder.decode());
+    match decoder.header() {
+        Some(&HeaderType::Request(ref h)) => {
+            assert_eq!(Opcode::Get, h.opcode);
+            assert_eq!(7, h.opaque);
+        }
+        _ => panic!(),
+    }
+    assert_eq!(5, decoder.needed());
+    decoder.feed(&contents[HEADER_LENGTH..]);
+    assert!(decoder.decode().unwrap().is_some());
+    assert_eq!(None, decoder.header());
+}
+
+#[test]
+fn errors_are_reported_as_soon_as_possible() {
+    let mut decoder = Decoder::new();
+    decoder.feed(&[0x82]);
+    assert_eq!(Err(ParseError::InvalidMagic(0x82)), decoder.decode());
+
+    let mut decoder = Decoder::new();
+    let contents = get(&[b'k'; 251], 0);
+    decoder.feed(&contents[..HEADER_LENGTH]);
+    assert_eq!(Err(ParseError::TooLarge { field: LengthField::Key, length: 251, limit: 250 }),
+               decoder.decode());
+    let mut decoder = Decoder::with_config(ParserConfig::unlimited());
+    decoder.feed(&contents);
+    assert!(decoder.decode().unwrap().is_some());
+}
+
+#[test]
+fn huge_bodies_are_refused_by_default() {
+    let mut header = RequestHeader::new(Opcode::Set);
+    header.key_length = 5;
+    header.extras_length = 8;
+    header.body_length = 0xffffffff;
+    let mut contents = Vec::new();
+    header.write_to(&mut contents).unwrap();
+    let mut decoder = Decoder::default();
+    decoder.feed(&contents);
+    match decoder.decode() {
+        Err(ParseError::TooLarge { field: LengthField::Body, .. }) => (),
+        other => panic!("{:?}", other),
+    }
+    assert!(decoder.needed() < 1024);
+}
@@ -0,0 +1,98 @@
+extern crate memcache_protocol;
+use memcache_protocol::*;
+
+// The keys of libmemcached's hash tests and the values its hashkit gives
+const KEYS: [&'static str; 25] = ["apple", "beat", "carrot", "daikon", "eggplant", "flower",
+                                  "green", "hide", "ick", "jack", "kick", "lime", "mushrooms",
+                                  "nectarine", "orange", "peach", "quant", "ripen", "strawberry",
+                                  "tang", "up", "volumne", "when", "yellow", "zip"];
+
+const ONE_AT_A_TIME: [u32; 25] = [2297466611, 3902465932, 469785835, 1937308741, 261917617,
+                                  3785641677, 1439605128, 1649152283, 1493851484, 1246520657,
+                                  2221159044, 1973511823, 384136800, 214358653, 2379473940,
+                                  4269788650, 2864377005, 2638630052, 427683330, 990491717,
+                                  1747111141, 792127364, 2599214128, 2553037199, 2509838425];
+
+const CRC32: [u32; 25] = [10542, 22009, 14526, 19510, 19432, 10199, 20634, 9369, 11511, 10362,
+                          7893, 31289, 11313, 9354, 7621, 30628, 15218, 25967, 2695, 9380,
+                          17300, 28156, 9192, 20484, 16925];
+
+const FNV1A_32: [u32; 25] = [280767167, 2421315013, 3072375666, 855001899, 459261019,
+                             3521085446, 18738364, 1625305005, 2162232970, 777243802, 3323728671,
+                             132336572, 3654473228, 260679466, 1169454059, 2698319462,
+                             1062177260, 235516991, 2218399068, 405302637, 1128467232,
+                             3579622413, 2138539289, 96429129, 2877453236];
+
+const MURMUR3: [u32; 25] = [1120212521, 1448785489, 4186307405, 2686268514, 444808887,
+                            221750260, 3074673162, 1946933257, 2826416675, 2430719166,
+                            3200429559, 297894347, 732888124, 4050076964, 3298336176,
+                            1336207361, 810553576, 3748182674, 3860119212, 3439537197,
+                            3044240981, 1464271804, 3896193724, 2915115798, 1702843840];
+
+fn nodes(count: usize) -> Vec<Node> {
+    (0..count).map(|i| Node::new(&format!("10.0.1.{}", i + 1), 11211)).collect()
+}
+
+fn hashes(hash: KeyHash) -> Vec<u32> {
+    KEYS.iter().map(|key| hash.hash(key.as_bytes())).collect()
+}
+
+#[test]
+fn key_hashes_match_libmemcached() {
+    assert_eq!(ONE_AT_A_TIME.to_vec(), hashes(KeyHash::OneAtATime));
+    assert_eq!(CRC32.to_vec(), hashes(KeyHash::Crc32));
+    assert_eq!(FNV1A_32.to_vec(), hashes(KeyHash::Fnv1a32));
+    assert_eq!(MURMUR3.to_vec(), hashes(KeyHash::Murmur3));
+    assert_eq!(Ketama::hash(b"apple"), KeyHash::Md5.hash(b"apple"));
+    assert_eq!(KeyHash::OneAtATime, KeyHash::default());
+}
+
+#[test]
+fn modula() {
+    let modula = Modula::new(&nodes(7), KeyHash::Crc32);
+    for (key, hash) in KEYS.iter().zip(CRC32.iter()) {
+        assert_eq!(Some(*hash as usize % 7), modula.node(key.as_bytes()));
+    }
+    assert_eq!(None, Modula::new(&[], KeyHash::Crc32).node(b"apple"));
+}
+
+#[test]
+fn jump_hash() {
+    // From the reference implementation in the paper
+    assert_eq!(0, JumpHash::bucket(12345, 1));
+    assert_eq!(29, JumpHash::bucket(12345, 100));
+    assert_eq!(938, JumpHash::bucket(12345, 1000));
+    assert_eq!(9, JumpHash::bucket(u64::MAX, 10));
+
+    // Adding a node only moves keys onto it
+    let mut jump = JumpHash::new(&nodes(4), KeyHash::Fnv1a32);
+    let before: Vec<_> = KEYS.iter().map(|key| jump.node(key.as_bytes()).unwrap()).collect();
+    jump.update(&nodes(5));
+    for (key, node) in KEYS.iter().zip(before) {
+        let after = jump.node(key.as_bytes()).unwrap();
+        assert!(after == node || after == 4);
+    }
+}
+
+#[test]
+fn rendezvous() {
+    let mut rendezvous = Rendezvous::new(&nodes(5), KeyHash::Murmur3);
+    let before: Vec<_> = KEYS.iter().map(|key| rendezvous.node(key.as_bytes()).unwrap()).collect();
+    assert!((0..5).all(|node| before.contains(&node)));
+
+    // Removing a node only moves its own keys
+    rendezvous.update(&nodes(5)[1..]);
+    for (key, node) in KEYS.iter().zip(before) {
+        if node != 0 {
+            assert_eq!(Some(node - 1), rendezvous.node(key.as_bytes()));
+        }
+    }
+
+    let mut weighted = nodes(2);
+    weighted[1].weight = 3;
+    let rendezvous = Rendezvous::new(&weighted, KeyHash::Murmur3);
+    let heavy = (0..4000)
+        .filter(|i| rendezvous.node(format!("key:{}", i).as_bytes()) == Some(1))
+        .count();
+    assert!(heavy > 2700 && heavy < 3300, "{} keys on the heavier node", heavy);
+}
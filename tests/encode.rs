@@ -0,0 +1,71 @@
+extern crate memcache_protocol;
+use memcache_protocol::*;
+
+#[test]
+fn encode_request_sets_lengths() {
+    let mut header = RequestHeader::new(Opcode::Set);
+    header.opaque = 0xdeadbeef;
+    header.cas = 7;
+    let request = Packet::new(HeaderType::Request(header),
+                              &[0xde, 0xad, 0xbe, 0xef, 0x00, 0x00, 0x0e, 0x10],
+                              b"Hello",
+                              b"World");
+    let bytes = request.encode().unwrap();
+    assert_eq!(&[0x80, 0x01, 0x00, 0x05,
+                 0x08, 0x00, 0x00, 0x00,
+                 0x00, 0x00, 0x00, 0x12,
+                 0xde, 0xad, 0xbe, 0xef,
+                 0x00, 0x00, 0x00, 0x00,
+                 0x00, 0x00, 0x00, 0x07][..],
+               &bytes[..HEADER_LENGTH]);
+    let (remaining, parsed) = packet(&bytes).unwrap();
+    assert_eq!(&b""[..], remaining);
+    assert_eq!(request, parsed);
+}
+
+#[test]
+fn encode_response_round_trip() {
+    let mut header = ResponseHeader::new(Opcode::Get, ResponseStatus::KeyNotFound);
+    header.opaque = 42;
+    let response = Packet::new(HeaderType::Response(header), b"", b"", b"Not found");
+    let bytes = response.encode().unwrap();
+    assert_eq!(HEADER_LENGTH + 9, bytes.len());
+    assert_eq!(response, packet(&bytes).unwrap().1);
+}
+
+#[test]
+fn encode_header_only() {
+    let mut header = RequestHeader::new(Opcode::Noop);
+    header.vbucket_id = 0x0102;
+    let mut bytes = Vec::new();
+    header.write_to(&mut bytes).unwrap();
+    assert_eq!(vec![0x80, 0x0a, 0x00, 0x00,
+                    0x00, 0x00, 0x01, 0x02,
+                    0x00, 0x00, 0x00, 0x00,
+                    0x00, 0x00, 0x00, 0x00,
+                    0x00, 0x00, 0x00, 0x00,
+                    0x00, 0x00, 0x00, 0x00],
+               bytes);
+}
+
+#[test]
+fn encode_rejects_mismatched_lengths() {
+    let mut header = RequestHeader::new(Opcode::Get);
+    header.key_length = 3;
+    header.body_length = 3;
+    let request = Packet {
+        header: HeaderType::Request(header),
+        extras: b"",
+        key: b"Hello",
+        body: b"",
+    };
+    assert!(request.encode().is_err());
+}
+
+#[test]
+fn encode_rejects_oversized_key() {
+    let key = vec![b'k'; 0x10000];
+    let request = Packet::new(HeaderType::Request(RequestHeader::new(Opcode::Get)),
+                              b"", &key, b"");
+    assert!(request.encode().is_err());
+}
@@ -0,0 +1,62 @@
+extern crate memcache_protocol;
+use memcache_protocol::*;
+
+const GET_REQUEST: &'static [u8] = &[0x80, 0x00, 0x00, 0x05,
+                                     0x00, 0x00, 0x00, 0x00,
+                                     0x00, 0x00, 0x00, 0x05,
+                                     0x00, 0x00, 0x00, 0x00,
+                                     0x00, 0x00, 0x00, 0x00,
+                                     0x00, 0x00, 0x00, 0x00,
+                                     b'H', b'e', b'l', b'l',
+                                     b'o'];
+
+#[test]
+fn invalid_magic() {
+    let mut contents = GET_REQUEST.to_vec();
+    contents[0] = 0x82;
+    assert_eq!(Err(ParseError::InvalidMagic(0x82)), packet(&contents).map(|_| ()));
+    assert_eq!(Err(ParseError::InvalidMagic(0x82)), packet(&contents[..1]).map(|_| ()));
+    assert_eq!("invalid magic 0x82 at byte 0",
+               ParseError::InvalidMagic(0x82).to_string());
+}
+
+#[test]
+fn unknown_opcode() {
+    let mut contents = GET_REQUEST.to_vec();
+    contents[1] = 0xfe;
+    assert_eq!(Err(ParseError::UnknownOpcode(0xfe)), packet(&contents).map(|_| ()));
+    assert_eq!(Err(ParseError::UnknownOpcode(0xfe)), packet(&contents[..2]).map(|_| ()));
+    assert!(packet_permissive(&contents).is_ok());
+    assert_eq!("unknown opcode 0xfe at byte 1",
+               ParseError::UnknownOpcode(0xfe).to_string());
+}
+
+#[test]
+fn incomplete() {
+    assert_eq!(Err(ParseError::Incomplete { needed: 24 }), packet(b"").map(|_| ()));
+    assert_eq!(Err(ParseError::Incomplete { needed: 14 }),
+               packet(&GET_REQUEST[..10]).map(|_| ()));
+    assert_eq!(Err(ParseError::Incomplete { needed: 1 }),
+               packet(&GET_REQUEST[..28]).map(|_| ()));
+    assert_eq!("incomplete packet, 1 more bytes needed",
+               ParseError::Incomplete { needed: 1 }.to_string());
+}
+
+#[test]
+fn length_mismatch() {
+    let mut contents = GET_REQUEST.to_vec();
+    contents[11] = 0x04;
+    let err = ParseError::LengthMismatch { body_length: 4, key_length: 5, extras_length: 0 };
+    assert_eq!(Err(err), packet(&contents).map(|_| ()));
+    assert_eq!("total body length 4 at byte 8 is smaller than key length 5 at byte 2 plus \
+                extras length 0 at byte 4",
+               err.to_string());
+}
+
+#[test]
+fn trailing_input_is_returned() {
+    let mut contents = GET_REQUEST.to_vec();
+    contents.extend_from_slice(&GET_REQUEST[..3]);
+    let (remaining, _) = packet(&contents).unwrap();
+    assert_eq!(&GET_REQUEST[..3], remaining);
+}
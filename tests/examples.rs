@@ -984,3 +984,653 @@ parsed_packet!(stat_response,
 // Value               : The textual string "3078"
   b"3078"
 );
+
+// A get sent before authenticating on a SASL enabled memcached:
+//   Byte/     0       |       1       |       2       |       3       |
+//      /              |               |               |               |
+//     |0 1 2 3 4 5 6 7|0 1 2 3 4 5 6 7|0 1 2 3 4 5 6 7|0 1 2 3 4 5 6 7|
+//     +---------------+---------------+---------------+---------------+
+//    0| 0x81          | 0x00          | 0x00          | 0x00          |
+//     +---------------+---------------+---------------+---------------+
+//    4| 0x00          | 0x00          | 0x00          | 0x20          |
+//     +---------------+---------------+---------------+---------------+
+//    8| 0x00          | 0x00          | 0x00          | 0x0d          |
+//     +---------------+---------------+---------------+---------------+
+//   12| 0x00          | 0x00          | 0x00          | 0x00          |
+//     +---------------+---------------+---------------+---------------+
+//   16| 0x00          | 0x00          | 0x00          | 0x00          |
+//     +---------------+---------------+---------------+---------------+
+//   20| 0x00          | 0x00          | 0x00          | 0x00          |
+//     +---------------+---------------+---------------+---------------+
+//   24| 0x41 ('A')    | 0x75 ('u')    | 0x74 ('t')    | 0x68 ('h')    |
+//     +---------------+---------------+---------------+---------------+
+//   28| 0x20 (' ')    | 0x66 ('f')    | 0x61 ('a')    | 0x69 ('i')    |
+//     +---------------+---------------+---------------+---------------+
+//   32| 0x6c ('l')    | 0x75 ('u')    | 0x72 ('r')    | 0x65 ('e')    |
+//     +---------------+---------------+---------------+---------------+
+//   36| 0x0a          |
+//     +---------------+
+//     Total 37 bytes (24 byte header, 13 byte body)
+parsed_packet!(auth_error_response,
+&[0x81, 0x00, 0x00, 0x00,
+  0x00, 0x00, 0x00, 0x20,
+  0x00, 0x00, 0x00, 0x0d,
+  0x00, 0x00, 0x00, 0x00,
+  0x00, 0x00, 0x00, 0x00,
+  0x00, 0x00, 0x00, 0x00,
+  b'A', b'u', b't', b'h',
+  b' ', b'f', b'a', b'i',
+  b'l', b'u', b'r', b'e',
+  b'\n'],
+// Field        (offset) (value)
+// Magic        (0)    : 0x81
+  Response,
+// Opcode       (1)    : 0x00
+  Get,
+// Key length   (2,3)  : 0x0000
+  0,
+// Extra length (4)    : 0x00
+  0,
+// Data type    (5)    : 0x00
+// Status       (6,7)  : 0x0020
+  AuthenticationError,
+// Total body   (8-11) : 0x0000000d
+  13,
+// Opaque       (12-15): 0x00000000
+  0,
+// CAS          (16-23): 0x0000000000000000
+  0,
+// Extras              : None
+  &b""[..],
+// Key                 : None
+  &b""[..],
+// Value        (24-36): The textual string "Auth failure\n"
+  b"Auth failure\n"
+);
+
+// Temporary failure with an error message:
+parsed_packet!(temporary_failure_response,
+&[0x81, 0x01, 0x00, 0x00,
+  0x00, 0x00, 0x00, 0x86,
+  0x00, 0x00, 0x00, 0x04,
+  0x00, 0x00, 0x00, 0x2a,
+  0x00, 0x00, 0x00, 0x00,
+  0x00, 0x00, 0x00, 0x00,
+  b'b', b'u', b's', b'y'],
+// Field        (offset) (value)
+// Magic        (0)    : 0x81
+  Response,
+// Opcode       (1)    : 0x01
+  Set,
+// Key length   (2,3)  : 0x0000
+  0,
+// Extra length (4)    : 0x00
+  0,
+// Data type    (5)    : 0x00
+// Status       (6,7)  : 0x0086
+  TemporaryFailure,
+// Total body   (8-11) : 0x00000004
+  4,
+// Opaque       (12-15): 0x0000002a
+  42,
+// CAS          (16-23): 0x0000000000000000
+  0,
+// Extras              : None
+  &b""[..],
+// Key                 : None
+  &b""[..],
+// Value        (24-27): The textual string "busy"
+  b"busy"
+);
+
+#[test]
+fn every_declared_status() {
+    let statuses = [(0x0000, ResponseStatus::NoError),
+                    (0x0001, ResponseStatus::KeyNotFound),
+                    (0x0002, ResponseStatus::KeyExists),
+                    (0x0003, ResponseStatus::ValueTooLarge),
+                    (0x0004, ResponseStatus::InvalidArguements),
+                    (0x0005, ResponseStatus::NotStored),
+                    (0x0006, ResponseStatus::NonNumeric),
+                    (0x0007, ResponseStatus::WrongServer),
+                    (0x0008, ResponseStatus::Unknown(0x0008)),
+                    (0x0009, ResponseStatus::Unknown(0x0009)),
+                    (0x0020, ResponseStatus::AuthenticationError),
+                    (0x0021, ResponseStatus::AuthenticationContinue),
+                    (0x0081, ResponseStatus::UnknownCommand),
+                    (0x0082, ResponseStatus::OutOfMemory),
+                    (0x0083, ResponseStatus::NotSupported),
+                    (0x0084, ResponseStatus::InternalError),
+                    (0x0085, ResponseStatus::Busy),
+                    (0x0086, ResponseStatus::TemporaryFailure),
+                    (0x00ff, ResponseStatus::Unknown(0x00ff)),
+                    (0xbeef, ResponseStatus::Unknown(0xbeef))];
+    for &(code, status) in statuses.iter() {
+        let mut contents = vec![0x81, 0x00, 0x00, 0x00,
+                                0x00, 0x00, 0x00, 0x00,
+                                0x00, 0x00, 0x00, 0x00,
+                                0x00, 0x00, 0x00, 0x00,
+                                0x00, 0x00, 0x00, 0x00,
+                                0x00, 0x00, 0x00, 0x00];
+        contents[6] = (code >> 8) as u8;
+        contents[7] = code as u8;
+        let (remaining, packet) = packet(&contents).unwrap();
+        assert_eq!(&b""[..], remaining);
+        match packet.header {
+            HeaderType::Response(ref h) => assert_eq!(status, h.status),
+            _ => panic!(),
+        }
+        assert_eq!(code, u16::from(status));
+        assert_eq!(contents, packet.encode().unwrap());
+    }
+}
+
+// A vendor specific request this crate doesn't know about, with 4 bytes of
+// extras, a 3 byte key and a 2 byte value, followed by a noop request.
+const UNKNOWN_OPCODE_REQUESTS: &'static [u8] = &[
+  0x80, 0xfe, 0x00, 0x03,
+  0x04, 0x00, 0x00, 0x00,
+  0x00, 0x00, 0x00, 0x09,
+  0x00, 0x00, 0x00, 0x07,
+  0x00, 0x00, 0x00, 0x00,
+  0x00, 0x00, 0x00, 0x00,
+  0x00, 0x00, 0x0e, 0x10,
+  b'f', b'o', b'o',
+  b'h', b'i',
+  0x80, 0x0a, 0x00, 0x00,
+  0x00, 0x00, 0x00, 0x00,
+  0x00, 0x00, 0x00, 0x00,
+  0x00, 0x00, 0x00, 0x08,
+  0x00, 0x00, 0x00, 0x00,
+  0x00, 0x00, 0x00, 0x00];
+
+#[test]
+fn unknown_opcode_is_rejected() {
+    assert!(packet(UNKNOWN_OPCODE_REQUESTS).is_err());
+}
+
+#[test]
+fn unknown_opcode_is_preserved() {
+    let (remaining, unknown) = packet_permissive(UNKNOWN_OPCODE_REQUESTS).unwrap();
+    match unknown.header {
+        HeaderType::Request(ref h) => {
+            assert_eq!(Opcode::Other(0xfe), h.opcode);
+            assert_eq!(7, h.opaque);
+        }
+        _ => panic!(),
+    }
+    assert_eq!(&[0x00, 0x00, 0x0e, 0x10][..], unknown.extras);
+    assert_eq!(&b"foo"[..], unknown.key);
+    assert_eq!(&b"hi"[..], unknown.body);
+    assert_eq!(&UNKNOWN_OPCODE_REQUESTS[..33], &unknown.encode().unwrap()[..]);
+
+    let (remaining, noop) = packet_permissive(remaining).unwrap();
+    assert_eq!(&b""[..], remaining);
+    match noop.header {
+        HeaderType::Request(ref h) => {
+            assert_eq!(Opcode::Noop, h.opcode);
+            assert_eq!(8, h.opaque);
+        }
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn unknown_opcode_response_is_preserved() {
+    let contents: &[u8] = &[0x81, 0xfe, 0x00, 0x00,
+                            0x00, 0x00, 0x00, 0x81,
+                            0x00, 0x00, 0x00, 0x0f,
+                            0x00, 0x00, 0x00, 0x00,
+                            0x00, 0x00, 0x00, 0x00,
+                            0x00, 0x00, 0x00, 0x00,
+                            b'U', b'n', b'k', b'n',
+                            b'o', b'w', b'n', b' ',
+                            b'c', b'o', b'm', b'm',
+                            b'a', b'n', b'd'];
+    let (remaining, response) = packet_permissive(contents).unwrap();
+    assert_eq!(&b""[..], remaining);
+    match response.header {
+        HeaderType::Response(ref h) => {
+            assert_eq!(Opcode::Other(0xfe), h.opcode);
+            assert_eq!(ResponseStatus::UnknownCommand, h.status);
+        }
+        _ => panic!(),
+    }
+    assert_eq!(&b"Unknown command"[..], response.body);
+}
+
+// verbosity request:
+//   Byte/     0       |       1       |       2       |       3       |
+//      /              |               |               |               |
+//     |0 1 2 3 4 5 6 7|0 1 2 3 4 5 6 7|0 1 2 3 4 5 6 7|0 1 2 3 4 5 6 7|
+//     +---------------+---------------+---------------+---------------+
+//    0| 0x80          | 0x1b          | 0x00          | 0x00          |
+//     +---------------+---------------+---------------+---------------+
+//    4| 0x04          | 0x00          | 0x00          | 0x00          |
+//     +---------------+---------------+---------------+---------------+
+//    8| 0x00          | 0x00          | 0x00          | 0x04          |
+//     +---------------+---------------+---------------+---------------+
+//   12| 0x00          | 0x00          | 0x00          | 0x00          |
+//     +---------------+---------------+---------------+---------------+
+//   16| 0x00          | 0x00          | 0x00          | 0x00          |
+//     +---------------+---------------+---------------+---------------+
+//   20| 0x00          | 0x00          | 0x00          | 0x00          |
+//     +---------------+---------------+---------------+---------------+
+//   24| 0x00          | 0x00          | 0x00          | 0x01          |
+//     +---------------+---------------+---------------+---------------+
+//     Total 28 bytes (24 byte header, 4 byte extras)
+parsed_packet!(verbosity_request,
+&[0x80, 0x1b, 0x00, 0x00,
+  0x04, 0x00, 0x00, 0x00,
+  0x00, 0x00, 0x00, 0x04,
+  0x00, 0x00, 0x00, 0x00,
+  0x00, 0x00, 0x00, 0x00,
+  0x00, 0x00, 0x00, 0x00,
+  0x00, 0x00, 0x00, 0x01],
+// Field        (offset) (value)
+// Magic        (0)    : 0x80
+  Request,
+// Opcode       (1)    : 0x1b
+  Verbosity,
+// Key length   (2,3)  : 0x0000
+  0,
+// Extra length (4)    : 0x04
+  4,
+// Data type    (5)    : 0x00
+// Reserved     (6,7)  : 0x0000
+// Total body   (8-11) : 0x00000004
+  4,
+// Opaque       (12-15): 0x00000000
+  0,
+// CAS          (16-23): 0x0000000000000000
+  0,
+// Extras              :
+//   Verbosity  (24-27): 0x00000001
+  &[0x00, 0x00, 0x00, 0x01],
+// Key                 : None
+  &b""[..],
+// Value               : None
+  &b""[..]
+);
+
+// touch request, moving the expiration of "Hello" to one hour:
+//   Byte/     0       |       1       |       2       |       3       |
+//      /              |               |               |               |
+//     |0 1 2 3 4 5 6 7|0 1 2 3 4 5 6 7|0 1 2 3 4 5 6 7|0 1 2 3 4 5 6 7|
+//     +---------------+---------------+---------------+---------------+
+//    0| 0x80          | 0x1c          | 0x00          | 0x05          |
+//     +---------------+---------------+---------------+---------------+
+//    4| 0x04          | 0x00          | 0x00          | 0x00          |
+//     +---------------+---------------+---------------+---------------+
+//    8| 0x00          | 0x00          | 0x00          | 0x09          |
+//     +---------------+---------------+---------------+---------------+
+//   12| 0x00          | 0x00          | 0x00          | 0x00          |
+//     +---------------+---------------+---------------+---------------+
+//   16| 0x00          | 0x00          | 0x00          | 0x00          |
+//     +---------------+---------------+---------------+---------------+
+//   20| 0x00          | 0x00          | 0x00          | 0x00          |
+//     +---------------+---------------+---------------+---------------+
+//   24| 0x00          | 0x00          | 0x0e          | 0x10          |
+//     +---------------+---------------+---------------+---------------+
+//   28| 0x48 ('H')    | 0x65 ('e')    | 0x6c ('l')    | 0x6c ('l')    |
+//     +---------------+---------------+---------------+---------------+
+//   32| 0x6f ('o')    |
+//     +---------------+
+//     Total 33 bytes (24 byte header, 4 byte extras, 5 byte key)
+parsed_packet!(touch_request,
+&[0x80, 0x1c, 0x00, 0x05,
+  0x04, 0x00, 0x00, 0x00,
+  0x00, 0x00, 0x00, 0x09,
+  0x00, 0x00, 0x00, 0x00,
+  0x00, 0x00, 0x00, 0x00,
+  0x00, 0x00, 0x00, 0x00,
+  0x00, 0x00, 0x0e, 0x10,
+  b'H', b'e', b'l', b'l',
+  b'o'],
+// Field        (offset) (value)
+// Magic        (0)    : 0x80
+  Request,
+// Opcode       (1)    : 0x1c
+  Touch,
+// Key length   (2,3)  : 0x0005
+  5,
+// Extra length (4)    : 0x04
+  4,
+// Data type    (5)    : 0x00
+// Reserved     (6,7)  : 0x0000
+// Total body   (8-11) : 0x00000009
+  9,
+// Opaque       (12-15): 0x00000000
+  0,
+// CAS          (16-23): 0x0000000000000000
+  0,
+// Extras              :
+//   Expiration (24-27): 0x00000e10
+  &[0x00, 0x00, 0x0e, 0x10],
+// Key          (28-32): The textual string "Hello"
+  b"Hello",
+// Value               : None
+  &b""[..]
+);
+
+// touch response:
+//   Byte/     0       |       1       |       2       |       3       |
+//      /              |               |               |               |
+//     |0 1 2 3 4 5 6 7|0 1 2 3 4 5 6 7|0 1 2 3 4 5 6 7|0 1 2 3 4 5 6 7|
+//     +---------------+---------------+---------------+---------------+
+//    0| 0x81          | 0x1c          | 0x00          | 0x00          |
+//     +---------------+---------------+---------------+---------------+
+//    4| 0x00          | 0x00          | 0x00          | 0x00          |
+//     +---------------+---------------+---------------+---------------+
+//    8| 0x00          | 0x00          | 0x00          | 0x00          |
+//     +---------------+---------------+---------------+---------------+
+//   12| 0x00          | 0x00          | 0x00          | 0x00          |
+//     +---------------+---------------+---------------+---------------+
+//   16| 0x00          | 0x00          | 0x00          | 0x00          |
+//     +---------------+---------------+---------------+---------------+
+//   20| 0x00          | 0x00          | 0x00          | 0x01          |
+//     +---------------+---------------+---------------+---------------+
+//     Total 24 bytes (24 byte header)
+parsed_packet!(touch_response,
+&[0x81, 0x1c, 0x00, 0x00,
+  0x00, 0x00, 0x00, 0x00,
+  0x00, 0x00, 0x00, 0x00,
+  0x00, 0x00, 0x00, 0x00,
+  0x00, 0x00, 0x00, 0x00,
+  0x00, 0x00, 0x00, 0x01],
+// Field        (offset) (value)
+// Magic        (0)    : 0x81
+  Response,
+// Opcode       (1)    : 0x1c
+  Touch,
+// Key length   (2,3)  : 0x0000
+  0,
+// Extra length (4)    : 0x00
+  0,
+// Data type    (5)    : 0x00
+// Status       (6,7)  : 0x0000
+  NoError,
+// Total body   (8-11) : 0x00000000
+  0,
+// Opaque       (12-15): 0x00000000
+  0,
+// CAS          (16-23): 0x0000000000000001
+  1,
+// Extras              : None
+  &b""[..],
+// Key                 : None
+  &b""[..],
+// Value               : None
+  &b""[..]
+);
+
+// gat request:
+//   Byte/     0       |       1       |       2       |       3       |
+//      /              |               |               |               |
+//     |0 1 2 3 4 5 6 7|0 1 2 3 4 5 6 7|0 1 2 3 4 5 6 7|0 1 2 3 4 5 6 7|
+//     +---------------+---------------+---------------+---------------+
+//    0| 0x80          | 0x1d          | 0x00          | 0x05          |
+//     +---------------+---------------+---------------+---------------+
+//    4| 0x04          | 0x00          | 0x00          | 0x00          |
+//     +---------------+---------------+---------------+---------------+
+//    8| 0x00          | 0x00          | 0x00          | 0x09          |
+//     +---------------+---------------+---------------+---------------+
+//   12| 0x00          | 0x00          | 0x00          | 0x00          |
+//     +---------------+---------------+---------------+---------------+
+//   16| 0x00          | 0x00          | 0x00          | 0x00          |
+//     +---------------+---------------+---------------+---------------+
+//   20| 0x00          | 0x00          | 0x00          | 0x00          |
+//     +---------------+---------------+---------------+---------------+
+//   24| 0x00          | 0x00          | 0x0e          | 0x10          |
+//     +---------------+---------------+---------------+---------------+
+//   28| 0x48 ('H')    | 0x65 ('e')    | 0x6c ('l')    | 0x6c ('l')    |
+//     +---------------+---------------+---------------+---------------+
+//   32| 0x6f ('o')    |
+//     +---------------+
+//     Total 33 bytes (24 byte header, 4 byte extras, 5 byte key)
+parsed_packet!(gat_request,
+&[0x80, 0x1d, 0x00, 0x05,
+  0x04, 0x00, 0x00, 0x00,
+  0x00, 0x00, 0x00, 0x09,
+  0x00, 0x00, 0x00, 0x00,
+  0x00, 0x00, 0x00, 0x00,
+  0x00, 0x00, 0x00, 0x00,
+  0x00, 0x00, 0x0e, 0x10,
+  b'H', b'e', b'l', b'l',
+  b'o'],
+// Field        (offset) (value)
+// Magic        (0)    : 0x80
+  Request,
+// Opcode       (1)    : 0x1d
+  GetAndTouch,
+// Key length   (2,3)  : 0x0005
+  5,
+// Extra length (4)    : 0x04
+  4,
+// Data type    (5)    : 0x00
+// Reserved     (6,7)  : 0x0000
+// Total body   (8-11) : 0x00000009
+  9,
+// Opaque       (12-15): 0x00000000
+  0,
+// CAS          (16-23): 0x0000000000000000
+  0,
+// Extras              :
+//   Expiration (24-27): 0x00000e10
+  &[0x00, 0x00, 0x0e, 0x10],
+// Key          (28-32): The textual string "Hello"
+  b"Hello",
+// Value               : None
+  &b""[..]
+);
+
+// gatq request:
+//   Byte/     0       |       1       |       2       |       3       |
+//      /              |               |               |               |
+//     |0 1 2 3 4 5 6 7|0 1 2 3 4 5 6 7|0 1 2 3 4 5 6 7|0 1 2 3 4 5 6 7|
+//     +---------------+---------------+---------------+---------------+
+//    0| 0x80          | 0x1e          | 0x00          | 0x05          |
+//     +---------------+---------------+---------------+---------------+
+//    4| 0x04          | 0x00          | 0x00          | 0x00          |
+//     +---------------+---------------+---------------+---------------+
+//    8| 0x00          | 0x00          | 0x00          | 0x09          |
+//     +---------------+---------------+---------------+---------------+
+//   12| 0x00          | 0x00          | 0x00          | 0x2a          |
+//     +---------------+---------------+---------------+---------------+
+//   16| 0x00          | 0x00          | 0x00          | 0x00          |
+//     +---------------+---------------+---------------+---------------+
+//   20| 0x00          | 0x00          | 0x00          | 0x00          |
+//     +---------------+---------------+---------------+---------------+
+//   24| 0x00          | 0x00          | 0x0e          | 0x10          |
+//     +---------------+---------------+---------------+---------------+
+//   28| 0x48 ('H')    | 0x65 ('e')    | 0x6c ('l')    | 0x6c ('l')    |
+//     +---------------+---------------+---------------+---------------+
+//   32| 0x6f ('o')    |
+//     +---------------+
+//     Total 33 bytes (24 byte header, 4 byte extras, 5 byte key)
+parsed_packet!(gatq_request,
+&[0x80, 0x1e, 0x00, 0x05,
+  0x04, 0x00, 0x00, 0x00,
+  0x00, 0x00, 0x00, 0x09,
+  0x00, 0x00, 0x00, 0x2a,
+  0x00, 0x00, 0x00, 0x00,
+  0x00, 0x00, 0x00, 0x00,
+  0x00, 0x00, 0x0e, 0x10,
+  b'H', b'e', b'l', b'l',
+  b'o'],
+// Field        (offset) (value)
+// Magic        (0)    : 0x80
+  Request,
+// Opcode       (1)    : 0x1e
+  GetAndTouchQ,
+// Key length   (2,3)  : 0x0005
+  5,
+// Extra length (4)    : 0x04
+  4,
+// Data type    (5)    : 0x00
+// Reserved     (6,7)  : 0x0000
+// Total body   (8-11) : 0x00000009
+  9,
+// Opaque       (12-15): 0x0000002a
+  42,
+// CAS          (16-23): 0x0000000000000000
+  0,
+// Extras              :
+//   Expiration (24-27): 0x00000e10
+  &[0x00, 0x00, 0x0e, 0x10],
+// Key          (28-32): The textual string "Hello"
+  b"Hello",
+// Value               : None
+  &b""[..]
+);
+
+// gat/gatq response:
+//   Byte/     0       |       1       |       2       |       3       |
+//      /              |               |               |               |
+//     |0 1 2 3 4 5 6 7|0 1 2 3 4 5 6 7|0 1 2 3 4 5 6 7|0 1 2 3 4 5 6 7|
+//     +---------------+---------------+---------------+---------------+
+//    0| 0x81          | 0x1d          | 0x00          | 0x00          |
+//     +---------------+---------------+---------------+---------------+
+//    4| 0x04          | 0x00          | 0x00          | 0x00          |
+//     +---------------+---------------+---------------+---------------+
+//    8| 0x00          | 0x00          | 0x00          | 0x09          |
+//     +---------------+---------------+---------------+---------------+
+//   12| 0x00          | 0x00          | 0x00          | 0x00          |
+//     +---------------+---------------+---------------+---------------+
+//   16| 0x00          | 0x00          | 0x00          | 0x00          |
+//     +---------------+---------------+---------------+---------------+
+//   20| 0x00          | 0x00          | 0x00          | 0x01          |
+//     +---------------+---------------+---------------+---------------+
+//   24| 0xde          | 0xad          | 0xbe          | 0xef          |
+//     +---------------+---------------+---------------+---------------+
+//   28| 0x57 ('W')    | 0x6f ('o')    | 0x72 ('r')    | 0x6c ('l')    |
+//     +---------------+---------------+---------------+---------------+
+//   32| 0x64 ('d')    |
+//     +---------------+
+//     Total 33 bytes (24 byte header, 4 byte extras, 5 byte value)
+parsed_packet!(gat_response,
+&[0x81, 0x1d, 0x00, 0x00,
+  0x04, 0x00, 0x00, 0x00,
+  0x00, 0x00, 0x00, 0x09,
+  0x00, 0x00, 0x00, 0x00,
+  0x00, 0x00, 0x00, 0x00,
+  0x00, 0x00, 0x00, 0x01,
+  0xde, 0xad, 0xbe, 0xef,
+  b'W', b'o', b'r', b'l',
+  b'd'],
+// Field        (offset) (value)
+// Magic        (0)    : 0x81
+  Response,
+// Opcode       (1)    : 0x1d
+  GetAndTouch,
+// Key length   (2,3)  : 0x0000
+  0,
+// Extra length (4)    : 0x04
+  4,
+// Data type    (5)    : 0x00
+// Status       (6,7)  : 0x0000
+  NoError,
+// Total body   (8-11) : 0x00000009
+  9,
+// Opaque       (12-15): 0x00000000
+  0,
+// CAS          (16-23): 0x0000000000000001
+  1,
+// Extras              :
+//   Flags      (24-27): 0xdeadbeef
+  &[0xde, 0xad, 0xbe, 0xef],
+// Key                 : None
+  &b""[..],
+// Value        (28-32): The textual string "World"
+  b"World"
+);
+
+// Hostile headers whose key and extras lengths don't fit in the total body
+// length. These used to underflow when computing the value length.
+fn malicious_header(magic: u8, key_length: u16, extras_length: u8, body_length: u32) -> Vec<u8> {
+    let mut contents = vec![magic, 0x00, (key_length >> 8) as u8, key_length as u8,
+                            extras_length, 0x00, 0x00, 0x00,
+                            (body_length >> 24) as u8, (body_length >> 16) as u8,
+                            (body_length >> 8) as u8, body_length as u8,
+                            0x00, 0x00, 0x00, 0x00,
+                            0x00, 0x00, 0x00, 0x00,
+                            0x00, 0x00, 0x00, 0x00];
+    contents.extend_from_slice(&[0x41; 64]);
+    contents
+}
+
+#[test]
+fn key_longer_than_body() {
+    let contents = malicious_header(0x80, 5, 0, 4);
+    assert_eq!(Err(ParseError::LengthMismatch { body_length: 4, key_length: 5, extras_length: 0 }),
+               packet(&contents).map(|_| ()));
+}
+
+#[test]
+fn extras_longer_than_body() {
+    let contents = malicious_header(0x81, 0, 8, 0);
+    assert_eq!(Err(ParseError::LengthMismatch { body_length: 0, key_length: 0, extras_length: 8 }),
+               packet(&contents).map(|_| ()));
+}
+
+#[test]
+fn key_and_extras_longer_than_body() {
+    let contents = malicious_header(0x80, 30, 20, 49);
+    assert_eq!(Err(ParseError::LengthMismatch { body_length: 49, key_length: 30, extras_length: 20 }),
+               packet(&contents).map(|_| ()));
+    assert_eq!(Err(ParseError::LengthMismatch { body_length: 49, key_length: 30, extras_length: 20 }),
+               packet_permissive(&contents).map(|_| ()));
+}
+
+#[test]
+fn maximum_lengths() {
+    let contents = malicious_header(0x80, 0xffff, 0xff, 0xffff);
+    assert_eq!(Err(ParseError::LengthMismatch {
+                   body_length: 0xffff,
+                   key_length: 0xffff,
+                   extras_length: 0xff,
+               }),
+               packet(&contents).map(|_| ()));
+    // Reported before the body arrives, so a reader can drop the connection
+    assert_eq!(Err(ParseError::LengthMismatch {
+                   body_length: 0xffff,
+                   key_length: 0xffff,
+                   extras_length: 0xff,
+               }),
+               packet(&contents[..24]).map(|_| ()));
+    let contents = malicious_header(0x80, 0xffff, 0xff, 0xffffffff);
+    assert_eq!(Err(ParseError::Incomplete { needed: 0xffffffff - 64 }),
+               packet(&contents).map(|_| ()));
+}
+
+#[test]
+fn exact_fit_is_accepted() {
+    let contents = malicious_header(0x80, 40, 24, 64);
+    let (remaining, packet) = packet(&contents).unwrap();
+    assert_eq!(&b""[..], remaining);
+    assert_eq!(24, packet.extras.len());
+    assert_eq!(40, packet.key.len());
+    assert_eq!(&b""[..], packet.body);
+}
+
+#[test]
+fn corrupted_length_fields_never_panic() {
+    let contents = malicious_header(0x80, 5, 8, 18);
+    for offset in [2, 3, 4, 8, 9, 10, 11].iter() {
+        for value in 0..256 {
+            let mut corrupted = contents.clone();
+            corrupted[*offset] = value as u8;
+            let _ = packet(&corrupted);
+            let _ = packet_permissive(&corrupted);
+        }
+    }
+}
+
+#[test]
+fn read_packet_rejects_mismatched_lengths() {
+    let contents = malicious_header(0x80, 0xffff, 0xff, 0xffff);
+    let mut buf = Vec::new();
+    let err = read_packet(&mut &contents[..], &mut buf).unwrap_err();
+    assert_eq!(std::io::ErrorKind::InvalidData, err.kind());
+    assert_eq!(HEADER_LENGTH, buf.len());
+}
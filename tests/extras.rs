@@ -0,0 +1,87 @@
+extern crate memcache_protocol;
+use memcache_protocol::*;
+
+fn request(opcode: Opcode, extras: &[u8]) -> Result<Extras, ExtrasLengthError> {
+    decode_extras(&HeaderType::Request(RequestHeader::new(opcode)), extras)
+}
+
+fn response(opcode: Opcode, status: ResponseStatus, extras: &[u8])
+            -> Result<Extras, ExtrasLengthError> {
+    decode_extras(&HeaderType::Response(ResponseHeader::new(opcode, status)), extras)
+}
+
+#[test]
+fn add_request_extras() {
+    let contents: &[u8] = &[0x80, 0x02, 0x00, 0x05,
+                            0x08, 0x00, 0x00, 0x00,
+                            0x00, 0x00, 0x00, 0x12,
+                            0x00, 0x00, 0x00, 0x00,
+                            0x00, 0x00, 0x00, 0x00,
+                            0x00, 0x00, 0x00, 0x00,
+                            0xde, 0xad, 0xbe, 0xef,
+                            0x00, 0x00, 0x0e, 0x10,
+                            b'H', b'e', b'l', b'l',
+                            b'o', b'W', b'o', b'r',
+                            b'l', b'd'];
+    let (_, packet) = packet(contents).unwrap();
+    assert_eq!(Ok(Extras::Storage { flags: 0xdeadbeef, expiration: 0x0e10 }),
+               packet.typed_extras());
+}
+
+#[test]
+fn increment_request_extras() {
+    let extras = [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+                  0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                  0x00, 0x00, 0x0e, 0x10];
+    assert_eq!(Ok(Extras::Counter { delta: 1, initial: 0, expiration: 0x0e10 }),
+               request(Opcode::DecrementQ, &extras));
+    assert_eq!(&extras[..], &Extras::Counter { delta: 1, initial: 0, expiration: 0x0e10 }
+                                .to_bytes()[..]);
+}
+
+#[test]
+fn flush_request_extras_are_optional() {
+    assert_eq!(Ok(Extras::Flush { expiration: None }), request(Opcode::Flush, b""));
+    assert_eq!(Ok(Extras::Flush { expiration: Some(0x0e10) }),
+               request(Opcode::FlushQ, &[0x00, 0x00, 0x0e, 0x10]));
+}
+
+#[test]
+fn get_response_extras() {
+    assert_eq!(Ok(Extras::Value { flags: 0xdeadbeef }),
+               response(Opcode::GetK, ResponseStatus::NoError, &[0xde, 0xad, 0xbe, 0xef]));
+    assert_eq!(Ok(Extras::None),
+               response(Opcode::Get, ResponseStatus::KeyNotFound, b""));
+}
+
+#[test]
+fn no_extras_expected() {
+    assert_eq!(Ok(Extras::None), request(Opcode::Get, b""));
+    assert_eq!(Ok(Extras::None), response(Opcode::Set, ResponseStatus::NoError, b""));
+    assert_eq!(Err(ExtrasLengthError { opcode: Opcode::Delete, expected: 0, actual: 4 }),
+               request(Opcode::Delete, &[0x00, 0x00, 0x0e, 0x10]));
+}
+
+#[test]
+fn wrong_extras_length() {
+    assert_eq!(Err(ExtrasLengthError { opcode: Opcode::Set, expected: 8, actual: 4 }),
+               request(Opcode::Set, &[0xde, 0xad, 0xbe, 0xef]));
+    assert_eq!(Err(ExtrasLengthError { opcode: Opcode::Increment, expected: 20, actual: 8 }),
+               request(Opcode::Increment, &[0; 8]));
+    assert_eq!(Err(ExtrasLengthError { opcode: Opcode::Flush, expected: 4, actual: 2 }),
+               request(Opcode::Flush, &[0; 2]));
+    assert_eq!(Err(ExtrasLengthError { opcode: Opcode::GetQ, expected: 4, actual: 0 }),
+               response(Opcode::GetQ, ResponseStatus::NoError, b""));
+}
+
+#[test]
+fn touch_extras() {
+    assert_eq!(Ok(Extras::Touch { expiration: 0x0e10 }),
+               request(Opcode::GetAndTouchQ, &[0x00, 0x00, 0x0e, 0x10]));
+    assert_eq!(Ok(Extras::Verbosity { level: 1 }),
+               request(Opcode::Verbosity, &[0x00, 0x00, 0x00, 0x01]));
+    assert_eq!(Ok(Extras::Value { flags: 1 }),
+               response(Opcode::GetAndTouch, ResponseStatus::NoError, &[0x00, 0x00, 0x00, 0x01]));
+    assert_eq!(Err(ExtrasLengthError { opcode: Opcode::Touch, expected: 4, actual: 0 }),
+               request(Opcode::Touch, b""));
+}
@@ -24,6 +24,19 @@ macro_rules! parsed_packet {
     fn $test() {
       let packet_contents: &[u8] = $packet;
       let (remaining, packet) = packet(packet_contents).unwrap();
+      assert_eq!(packet_contents, &packet.encode().unwrap()[..]);
+      let mut decoder = Decoder::new();
+      for (i, byte) in packet_contents.iter().enumerate() {
+        assert_eq!(Ok(None), decoder.decode());
+        if i < HEADER_LENGTH {
+          assert_eq!(HEADER_LENGTH - i, decoder.needed());
+        } else {
+          assert_eq!(packet_contents.len() - i, decoder.needed());
+        }
+        decoder.feed(&[*byte]);
+      }
+      assert_eq!(Ok(Some(packet.clone())), decoder.decode());
+      assert_eq!(0, decoder.buffered());
       let header = match packet.header {
         HeaderType::Response(h) => h,
         _ => panic!()
@@ -51,6 +64,19 @@ macro_rules! parsed_packet {
       let result = packet(packet_contents);
       println!("{:?}", result);
       let (remaining, packet) = result.unwrap();
+      assert_eq!(packet_contents, &packet.encode().unwrap()[..]);
+      let mut decoder = Decoder::new();
+      for (i, byte) in packet_contents.iter().enumerate() {
+        assert_eq!(Ok(None), decoder.decode());
+        if i < HEADER_LENGTH {
+          assert_eq!(HEADER_LENGTH - i, decoder.needed());
+        } else {
+          assert_eq!(packet_contents.len() - i, decoder.needed());
+        }
+        decoder.feed(&[*byte]);
+      }
+      assert_eq!(Ok(Some(packet.clone())), decoder.decode());
+      assert_eq!(0, decoder.buffered());
       let header = match packet.header {
         HeaderType::Request(h) => h,
         _ => panic!()
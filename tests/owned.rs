@@ -0,0 +1,55 @@
+extern crate bytes;
+extern crate memcache_protocol;
+use memcache_protocol::*;
+
+use bytes::Bytes;
+use std::thread;
+
+fn set_request() -> Vec<u8> {
+    Command::Set {
+        key: b"Hello".to_vec(),
+        value: b"World".to_vec(),
+        flags: 0xdeadbeef,
+        expiration: 3600,
+        cas: 0,
+        quiet: false,
+    }.encode(1).unwrap()
+}
+
+#[test]
+fn from_packet() {
+    let contents = set_request();
+    let owned = {
+        let (_, request) = packet(&contents).unwrap();
+        OwnedPacket::from(request)
+    };
+    let (_, request) = packet(&contents).unwrap();
+    assert_eq!(request, owned.packet());
+    assert_eq!(request.typed_extras(), owned.typed_extras());
+    assert_eq!(contents, owned.encode().unwrap());
+
+    // Not tied to the buffer it came from
+    let handle = thread::spawn(move || Command::from_packet(&owned.packet()).unwrap());
+    assert_eq!(Command::from_packet(&request).unwrap(), handle.join().unwrap());
+}
+
+#[test]
+fn split_from_shares_the_buffer() {
+    let mut contents = set_request();
+    contents.extend(Command::Noop.encode(2).unwrap());
+    contents.extend(&set_request()[..30]);
+    let mut buf = Bytes::from(contents);
+    let config = ParserConfig::default();
+
+    let set = OwnedPacket::split_from(&mut buf, &config).unwrap();
+    assert_eq!(&b"Hello"[..], &set.key[..]);
+    assert_eq!(&b"World"[..], &set.body[..]);
+    assert_eq!(Ok(Extras::Storage { flags: 0xdeadbeef, expiration: 3600 }),
+               set.typed_extras());
+    let noop = OwnedPacket::split_from(&mut buf, &config).unwrap();
+    assert_eq!(Ok(Command::Noop), Command::from_packet(&noop.packet()));
+
+    assert_eq!(Err(ParseError::Incomplete { needed: 12 }),
+               OwnedPacket::split_from(&mut buf, &config));
+    assert_eq!(30, buf.len());
+}
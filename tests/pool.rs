@@ -0,0 +1,115 @@
+extern crate memcache_protocol;
+use memcache_protocol::*;
+
+use std::io;
+use std::net::{Shutdown, TcpListener};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn start(config: PoolConfig) -> Pool {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || serve(listener, Arc::new(MemoryStore::new())));
+    Pool::new(&address, config)
+}
+
+fn config(max_size: usize) -> PoolConfig {
+    PoolConfig {
+        max_size: max_size,
+        checkout_timeout: Some(Duration::from_millis(100)),
+        ..PoolConfig::default()
+    }
+}
+
+#[test]
+fn connections_are_reused() {
+    let pool = start(config(2));
+    pool.get().unwrap().set(b"Hello", b"World", 0, 0).unwrap();
+    {
+        let mut first = pool.get().unwrap();
+        let mut second = pool.get().unwrap();
+        assert!(first.get(b"Hello").unwrap().is_some());
+        second.noop().unwrap();
+        assert_eq!((2, 0), (pool.stats().open, pool.stats().idle));
+    }
+    let stats = pool.stats();
+    assert_eq!((3, 2, 2, 0), (stats.checkouts, stats.connects, stats.idle, stats.waits));
+}
+
+#[test]
+fn checkouts_wait_for_a_connection() {
+    let pool = Arc::new(start(config(1)));
+    let held = pool.get().unwrap();
+    match pool.get() {
+        Err(ClientError::Io(ref err)) if err.kind() == io::ErrorKind::TimedOut => (),
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+
+    let waiting = Arc::clone(&pool);
+    let waiter = thread::spawn(move || waiting.get().map(|mut client| client.noop()).is_ok());
+    thread::sleep(Duration::from_millis(20));
+    drop(held);
+    assert!(waiter.join().unwrap());
+    let stats = pool.stats();
+    assert_eq!((2, 1, 1), (stats.waits, stats.timeouts, stats.connects));
+}
+
+#[test]
+fn broken_and_stale_connections_are_replaced() {
+    let pool = start(config(1));
+    pool.get().unwrap().get_ref().shutdown(Shutdown::Both).unwrap();
+    pool.get().unwrap().noop().unwrap();
+    assert_eq!(1, pool.stats().failed_health_checks);
+
+    let result: Result<(), ClientError> = pool.run(|client| {
+        client.noop()?;
+        Err(ClientError::Protocol("out of step".to_string()))
+    });
+    assert!(result.is_err());
+    assert_eq!((1, 0), (pool.stats().broken, pool.stats().open));
+    assert_eq!(Some(ResponseStatus::KeyNotFound),
+               pool.run(|client| client.delete(b"missing")).unwrap_err().status());
+    assert_eq!((1, 1), (pool.stats().open, pool.stats().idle));
+
+    let pool = start(PoolConfig { idle_timeout: Some(Duration::from_millis(0)), ..config(1) });
+    pool.get().unwrap();
+    pool.get().unwrap();
+    assert_eq!((1, 2), (pool.stats().expired, pool.stats().connects));
+}
+
+// Accepts connections and never answers.
+fn hung_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || {
+        let connections: Vec<_> = listener.incoming().collect();
+        drop(connections);
+    });
+    address
+}
+
+#[test]
+fn hung_servers_time_out() {
+    let config = PoolConfig { io_timeout: Some(Duration::from_millis(50)), ..config(1) };
+    let pool = Pool::new(&hung_server(), config);
+    let started = Instant::now();
+    pool.get().unwrap();
+    // The health check of the idle connection times out, and so does using
+    // the new one
+    let err = pool.run(|client| client.noop()).unwrap_err();
+    assert!(err.is_connection_error());
+    assert!(started.elapsed() < Duration::from_secs(1));
+    let stats = pool.stats();
+    assert_eq!((1, 1, 2), (stats.failed_health_checks, stats.broken, stats.connects));
+    assert_eq!(0, stats.open);
+}
+
+#[test]
+fn connection_errors() {
+    let io = io::Error::new(io::ErrorKind::ConnectionReset, "reset");
+    assert!(ClientError::Io(io).is_connection_error());
+    assert!(ClientError::Protocol(String::new()).is_connection_error());
+    assert!(!ClientError::Status(ResponseStatus::KeyNotFound, String::new()).is_connection_error());
+    assert!(!ClientError::NoServers.is_connection_error());
+}
@@ -0,0 +1,138 @@
+extern crate memcache_protocol;
+use memcache_protocol::*;
+
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+const CHALLENGE: &'static str = "<1896.697170952@postoffice.reston.mci.net>";
+
+// Accepts a single connection and answers SASL commands like memcached with
+// the user "tim" and password "tanstaaftanstaaf".
+fn stand_in_server(mechanisms: &'static str) -> (TcpStream, thread::JoinHandle<Vec<Command>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut received = Vec::new();
+        let mut buf = Vec::new();
+        while read_packet(&mut stream, &mut buf).is_ok() {
+            let (_, request) = packet(&buf).unwrap();
+            let command = Command::from_packet(&request).unwrap();
+            let reply = match command {
+                Command::SaslListMechanisms => Reply::Mechanisms(mechanisms.to_string()),
+                Command::SaslAuth { ref mechanism, ref data } if mechanism == b"PLAIN" => {
+                    if &data[..] == b"\0tim\0tanstaaftanstaaf" {
+                        Reply::Authenticated(b"Authenticated".to_vec())
+                    } else {
+                        Reply::Error(ResponseStatus::AuthenticationError,
+                                     "Auth failure".to_string())
+                    }
+                }
+                Command::SaslAuth { .. } => {
+                    Reply::Challenge(CHALLENGE.as_bytes().to_vec())
+                }
+                Command::SaslStep { ref data, .. } => {
+                    if &data[..] == b"tim b913a602c7eda7a495b4e6e7334d3890" {
+                        Reply::Authenticated(b"Authenticated".to_vec())
+                    } else {
+                        Reply::Error(ResponseStatus::AuthenticationError,
+                                     "Auth failure".to_string())
+                    }
+                }
+                _ => Reply::Error(ResponseStatus::UnknownCommand, "Unknown command".to_string()),
+            };
+            reply.write_to(command.opcode(), 0, &mut stream).unwrap();
+            received.push(command);
+        }
+        received
+    });
+    (TcpStream::connect(address).unwrap(), server)
+}
+
+#[test]
+fn cram_md5_rfc_2195() {
+    assert_eq!(&b"tim b913a602c7eda7a495b4e6e7334d3890"[..],
+               &cram_md5_response(b"tim", b"tanstaaftanstaaf", CHALLENGE.as_bytes())[..]);
+}
+
+#[test]
+fn plain_handshake() {
+    let (mut stream, server) = stand_in_server("PLAIN");
+    Authenticator::new("tim", "tanstaaftanstaaf").authenticate(&mut stream).unwrap();
+    drop(stream);
+    assert_eq!(vec![Command::SaslListMechanisms,
+                    Command::SaslAuth {
+                        mechanism: b"PLAIN".to_vec(),
+                        data: b"\0tim\0tanstaaftanstaaf".to_vec(),
+                    }],
+               server.join().unwrap());
+}
+
+#[test]
+fn cram_md5_handshake() {
+    let (mut stream, server) = stand_in_server("CRAM-MD5 PLAIN");
+    Authenticator::new("tim", "tanstaaftanstaaf").authenticate(&mut stream).unwrap();
+    drop(stream);
+    let received = server.join().unwrap();
+    assert_eq!(3, received.len());
+    assert_eq!(Command::SaslAuth { mechanism: b"CRAM-MD5".to_vec(), data: Vec::new() },
+               received[1]);
+}
+
+#[test]
+fn requested_mechanism() {
+    let (mut stream, server) = stand_in_server("CRAM-MD5 PLAIN");
+    Authenticator::new("tim", "tanstaaftanstaaf")
+        .with_mechanism(Mechanism::Plain)
+        .authenticate(&mut stream)
+        .unwrap();
+    drop(stream);
+    assert_eq!(2, server.join().unwrap().len());
+}
+
+#[test]
+fn wrong_password() {
+    let (mut stream, _) = stand_in_server("CRAM-MD5");
+    match Authenticator::new("tim", "guess").authenticate(&mut stream) {
+        Err(SaslError::Failed(ResponseStatus::AuthenticationError, _)) => (),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn unsupported_mechanism() {
+    let (mut stream, _) = stand_in_server("SCRAM-SHA-1");
+    match Authenticator::new("tim", "tanstaaftanstaaf").authenticate(&mut stream) {
+        Err(SaslError::Unsupported(ref offered)) if offered == "SCRAM-SHA-1" => (),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn unexpected_reply() {
+    let mut authenticator = Authenticator::new("tim", "tanstaaftanstaaf");
+    assert_eq!(Command::SaslListMechanisms, authenticator.start());
+    match authenticator.receive(Reply::Noop) {
+        Err(SaslError::Protocol(_)) => (),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn challenges_are_bytes() {
+    let challenge = vec![0xff, 0x00, 0xc3, 0x28];
+    let encoded = Reply::Challenge(challenge.clone()).encode(Opcode::SaslAuth, 0).unwrap();
+    let (_, response) = packet(&encoded).unwrap();
+    let reply = Reply::from_packet(&response).unwrap();
+    assert_eq!(Reply::Challenge(challenge.clone()), reply);
+
+    let mut authenticator = Authenticator::new("tim", "tanstaaftanstaaf")
+        .with_mechanism(Mechanism::CramMd5);
+    authenticator.start();
+    authenticator.receive(Reply::Mechanisms("CRAM-MD5".to_string())).unwrap();
+    let step = Command::SaslStep {
+        mechanism: b"CRAM-MD5".to_vec(),
+        data: cram_md5_response(b"tim", b"tanstaaftanstaaf", &challenge),
+    };
+    assert_eq!(Step::Send(step), authenticator.receive(reply).unwrap());
+}
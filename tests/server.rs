@@ -0,0 +1,145 @@
+extern crate memcache_protocol;
+use memcache_protocol::*;
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+// Only gets, sets and deletes, enough to see what the connection loop does
+// around the handler.
+#[derive(Default)]
+struct Map {
+    items: Mutex<HashMap<Vec<u8>, Vec<u8>>>,
+}
+
+impl Handler for Map {
+    fn handle(&self, command: Command) -> Reply {
+        let mut items = self.items.lock().unwrap();
+        match command {
+            Command::Get { key, .. } | Command::GetAndTouch { key, .. } => {
+                match items.get(&key) {
+                    Some(value) => {
+                        Reply::Value {
+                            flags: 0,
+                            cas: 0,
+                            key: Vec::new(),
+                            value: value.clone(),
+                        }
+                    }
+                    None => Reply::Error(ResponseStatus::KeyNotFound, "Not found".to_string()),
+                }
+            }
+            Command::Set { key, value, .. } => {
+                items.insert(key, value);
+                Reply::Stored { cas: 0 }
+            }
+            Command::Delete { key, .. } => {
+                match items.remove(&key) {
+                    Some(_) => Reply::Deleted { cas: 0 },
+                    None => Reply::Error(ResponseStatus::KeyNotFound, "Not found".to_string()),
+                }
+            }
+            Command::Noop => Reply::Noop,
+            _ => Reply::Error(ResponseStatus::NotSupported, "Not supported".to_string()),
+        }
+    }
+
+    fn stats(&self, _group: &[u8]) -> Vec<(String, String)> {
+        vec![("curr_items".to_string(), self.items.lock().unwrap().len().to_string())]
+    }
+}
+
+fn start() -> TcpStream {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    thread::spawn(move || serve(listener, Arc::new(Map::default())));
+    TcpStream::connect(address).unwrap()
+}
+
+fn response(stream: &mut TcpStream) -> (Opcode, u32, Reply) {
+    let config = ParserConfig { allow_unknown_opcodes: true, ..ParserConfig::unlimited() };
+    let mut buf = Vec::new();
+    read_packet_with_config(stream, &mut buf, &config).unwrap();
+    let (_, response) = packet_permissive(&buf).unwrap();
+    let reply = Reply::from_packet(&response).unwrap();
+    match response.header {
+        HeaderType::Response(ref h) => (h.opcode, h.opaque, reply),
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn client_against_the_server() {
+    let mut client = Client::new(start());
+    assert_eq!(None, client.get(b"Hello").unwrap());
+    client.set(b"Hello", b"World", 0, 0).unwrap();
+    assert_eq!(b"World".to_vec(), client.get(b"Hello").unwrap().unwrap().value);
+    let items = client.get_multi(vec!["Hello", "missing"]).unwrap();
+    assert_eq!(1, items.len());
+    assert_eq!(b"World".to_vec(), items[&b"Hello"[..]].value);
+    assert_eq!("1", client.stats(b"").unwrap()["curr_items"]);
+    let failures = client.delete_multi(vec!["Hello", "missing"]).unwrap();
+    assert_eq!(1, failures[0].index);
+    assert_eq!(Some(ResponseStatus::NotSupported), client.version().unwrap_err().status());
+    client.quit().unwrap();
+}
+
+#[test]
+fn quiet_replies_are_suppressed() {
+    let mut stream = start();
+    let set = Command::Set {
+        key: b"Hello".to_vec(),
+        value: b"World".to_vec(),
+        flags: 0,
+        expiration: 0,
+        cas: 0,
+        quiet: true,
+    };
+    let get = |key: &str| {
+        Command::Get { key: key.as_bytes().to_vec(), quiet: true, return_key: true }
+    };
+    let mut requests = Vec::new();
+    set.write_to(1, &mut requests).unwrap();
+    get("missing").write_to(2, &mut requests).unwrap();
+    get("Hello").write_to(3, &mut requests).unwrap();
+    Command::GetAndTouch { key: b"missing".to_vec(), expiration: 0, quiet: true }
+        .write_to(6, &mut requests)
+        .unwrap();
+    Command::Delete { key: b"missing".to_vec(), cas: 0, quiet: true }
+        .write_to(4, &mut requests)
+        .unwrap();
+    Command::Noop.write_to(5, &mut requests).unwrap();
+    stream.write_all(&requests).unwrap();
+
+    let hit = Reply::Value {
+        flags: 0,
+        cas: 0,
+        key: b"Hello".to_vec(),
+        value: b"World".to_vec(),
+    };
+    assert_eq!((Opcode::GetKQ, 3, hit), response(&mut stream));
+    match response(&mut stream) {
+        (Opcode::DeleteQ, 4, Reply::Error(ResponseStatus::KeyNotFound, _)) => (),
+        other => panic!("{:?}", other),
+    }
+    assert_eq!((Opcode::Noop, 5, Reply::Noop), response(&mut stream));
+}
+
+#[test]
+fn unknown_opcodes_and_quit() {
+    let mut stream = start();
+    let mut request = Command::Noop.encode(7).unwrap();
+    request[1] = 0xfe;
+    stream.write_all(&request).unwrap();
+    match response(&mut stream) {
+        (Opcode::Other(0xfe), 7, Reply::Error(ResponseStatus::UnknownCommand, _)) => (),
+        other => panic!("{:?}", other),
+    }
+
+    Command::Quit { quiet: true }.write_to(8, &mut stream).unwrap();
+    let mut rest = Vec::new();
+    stream.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+}
@@ -0,0 +1,304 @@
+extern crate memcache_protocol;
+use memcache_protocol::*;
+
+use std::collections::HashMap;
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+const NOW: u64 = 1_500_000_000;
+
+fn store() -> (MemoryStore, Arc<AtomicU64>) {
+    let now = Arc::new(AtomicU64::new(NOW));
+    let clock = Arc::clone(&now);
+    (MemoryStore::with_clock(move || clock.load(Ordering::SeqCst)), now)
+}
+
+fn set(key: &str, value: &str, expiration: u32, cas: u64) -> Command {
+    Command::Set {
+        key: key.as_bytes().to_vec(),
+        value: value.as_bytes().to_vec(),
+        flags: 7,
+        expiration: expiration,
+        cas: cas,
+        quiet: false,
+    }
+}
+
+fn get(key: &str) -> Command {
+    Command::Get { key: key.as_bytes().to_vec(), quiet: false, return_key: false }
+}
+
+fn value(store: &MemoryStore, key: &str) -> Option<Vec<u8>> {
+    match store.handle(get(key)) {
+        Reply::Value { value, .. } => Some(value),
+        Reply::Error(ResponseStatus::KeyNotFound, _) => None,
+        reply => panic!("{:?}", reply),
+    }
+}
+
+fn cas(reply: Reply) -> u64 {
+    match reply {
+        Reply::Stored { cas } => cas,
+        reply => panic!("{:?}", reply),
+    }
+}
+
+fn stats(store: &MemoryStore, group: &str) -> HashMap<String, String> {
+    store.stats(group.as_bytes()).into_iter().collect()
+}
+
+#[test]
+fn storage_rules() {
+    let (store, _) = store();
+    let first = cas(store.handle(set("a", "1", 0, 0)));
+    let second = cas(store.handle(set("a", "2", 0, 0)));
+    assert!(first != second);
+    assert_eq!(ResponseStatus::KeyExists, store.handle(set("a", "3", 0, first)).status());
+    assert_eq!(ResponseStatus::KeyNotFound, store.handle(set("b", "3", 0, first)).status());
+    cas(store.handle(set("a", "3", 0, second)));
+
+    let add = |key: &str| {
+        store.handle(Command::Add {
+            key: key.as_bytes().to_vec(),
+            value: b"added".to_vec(),
+            flags: 0,
+            expiration: 0,
+            quiet: false,
+        })
+    };
+    assert_eq!(ResponseStatus::KeyExists, add("a").status());
+    cas(add("b"));
+
+    let replace = Command::Replace {
+        key: b"c".to_vec(),
+        value: b"replaced".to_vec(),
+        flags: 0,
+        expiration: 0,
+        cas: 0,
+        quiet: false,
+    };
+    assert_eq!(ResponseStatus::KeyNotFound, store.handle(replace).status());
+
+    let append = |key: &str, value: &str| {
+        store.handle(Command::Append {
+            key: key.as_bytes().to_vec(),
+            value: value.as_bytes().to_vec(),
+            cas: 0,
+            quiet: false,
+        })
+    };
+    assert_eq!(ResponseStatus::NotStored, append("c", "!").status());
+    cas(append("a", "4"));
+    store.handle(Command::Prepend {
+        key: b"a".to_vec(),
+        value: b"2".to_vec(),
+        cas: 0,
+        quiet: false,
+    });
+    match store.handle(get("a")) {
+        Reply::Value { flags: 7, value, .. } => assert_eq!(b"234".to_vec(), value),
+        reply => panic!("{:?}", reply),
+    }
+
+    let delete = |cas| store.handle(Command::Delete { key: b"a".to_vec(), cas: cas, quiet: false });
+    assert_eq!(ResponseStatus::KeyExists, delete(first).status());
+    assert_eq!(Reply::Deleted { cas: 0 }, delete(0));
+    assert_eq!(ResponseStatus::KeyNotFound, delete(0).status());
+}
+
+#[test]
+fn expiration() {
+    let (store, now) = store();
+    store.handle(set("relative", "", 10, 0));
+    store.handle(set("absolute", "", (NOW + 100) as u32, 0));
+    store.handle(set("past", "", (NOW - 100) as u32, 0));
+    store.handle(set("forever", "", 0, 0));
+    assert_eq!(None, value(&store, "past"));
+
+    now.store(NOW + 10, Ordering::SeqCst);
+    assert_eq!(None, value(&store, "relative"));
+    assert!(value(&store, "absolute").is_some());
+    let touch = Command::Touch { key: b"absolute".to_vec(), expiration: 200 };
+    match store.handle(touch) {
+        Reply::Touched { .. } => (),
+        reply => panic!("{:?}", reply),
+    }
+
+    now.store(NOW + 100, Ordering::SeqCst);
+    assert!(value(&store, "absolute").is_some());
+    now.store(NOW + 210, Ordering::SeqCst);
+    assert_eq!(None, value(&store, "absolute"));
+    assert!(value(&store, "forever").is_some());
+}
+
+#[test]
+fn counters() {
+    let (store, _) = store();
+    let incr = |delta, expiration| {
+        let reply = store.handle(Command::Incr {
+            key: b"counter".to_vec(),
+            delta: delta,
+            initial: 5,
+            expiration: expiration,
+            quiet: false,
+        });
+        match reply {
+            Reply::Counter { value, cas } => Ok((value, cas)),
+            reply => Err(reply.status()),
+        }
+    };
+    assert_eq!(Err(ResponseStatus::KeyNotFound), incr(1, 0xffffffff));
+    assert_eq!(5, incr(1, 0).unwrap().0);
+    assert_eq!(6, incr(1, 0).unwrap().0);
+    let (value, cas) = incr(u64::MAX - 1, 0).unwrap();
+    assert_eq!(4, value);
+    match store.handle(get("counter")) {
+        Reply::Value { cas: stored, value, .. } => {
+            assert_eq!((cas, b"4".to_vec()), (stored, value))
+        }
+        reply => panic!("{:?}", reply),
+    }
+
+    let decr = store.handle(Command::Decr {
+        key: b"counter".to_vec(),
+        delta: 10,
+        initial: 0,
+        expiration: 0,
+        quiet: false,
+    });
+    match decr {
+        Reply::Counter { value: 0, .. } => (),
+        reply => panic!("{:?}", reply),
+    }
+
+    store.handle(set("counter", "text", 0, 0));
+    assert_eq!(Err(ResponseStatus::NonNumeric), incr(1, 0));
+}
+
+#[test]
+fn delayed_flush() {
+    let (store, now) = store();
+    store.handle(set("a", "", 0, 0));
+    let flush = |expiration| store.handle(Command::Flush { expiration: expiration, quiet: false });
+    assert_eq!(Reply::Flushed, flush(Some(10)));
+    store.handle(set("b", "", 0, 0));
+    assert!(value(&store, "a").is_some());
+
+    now.store(NOW + 10, Ordering::SeqCst);
+    assert_eq!(None, value(&store, "a"));
+    assert_eq!(None, value(&store, "b"));
+    store.handle(set("c", "", 0, 0));
+    now.store(NOW + 20, Ordering::SeqCst);
+    assert!(value(&store, "c").is_some());
+
+    flush(None);
+    assert!(store.is_empty());
+}
+
+#[test]
+fn value_too_large() {
+    let store = MemoryStore::new().with_max_value_length(4);
+    assert_eq!(ResponseStatus::ValueTooLarge, store.handle(set("a", "large", 0, 0)).status());
+    store.handle(set("a", "1234", 0, 0));
+    let append = Command::Append { key: b"a".to_vec(), value: b"5".to_vec(), cas: 0, quiet: false };
+    assert_eq!(ResponseStatus::ValueTooLarge, store.handle(append).status());
+}
+
+#[test]
+fn least_recently_used_are_evicted() {
+    // Each item takes 48 bytes of overhead, a 1 byte key and 100 byte value
+    let store = MemoryStore::new().with_memory_limit(3 * 149);
+    let data = "v".repeat(100);
+    for key in &["a", "b", "c"] {
+        cas(store.handle(set(key, &data, 0, 0)));
+    }
+    assert!(value(&store, "a").is_some());
+    cas(store.handle(set("d", &data, 0, 0)));
+    assert_eq!(None, value(&store, "b"));
+    assert!(value(&store, "a").is_some());
+    assert_eq!(3, store.len());
+
+    let general = stats(&store, "");
+    assert_eq!("1", general["evictions"]);
+    assert_eq!("447", general["bytes"]);
+
+    // Failing to store leaves everything as it was, the old value included
+    let too_large = "v".repeat(500);
+    assert_eq!(ResponseStatus::OutOfMemory, store.handle(set("e", &too_large, 0, 0)).status());
+    assert_eq!(ResponseStatus::OutOfMemory, store.handle(set("a", &too_large, 0, 0)).status());
+    let append = Command::Append {
+        key: b"a".to_vec(),
+        value: too_large.into_bytes(),
+        cas: 0,
+        quiet: false,
+    };
+    assert_eq!(ResponseStatus::OutOfMemory, store.handle(append).status());
+    assert_eq!(3, store.len());
+    for key in &["a", "c", "d"] {
+        assert_eq!(Some(data.clone().into_bytes()), value(&store, key));
+    }
+    let after = stats(&store, "");
+    assert_eq!((&general["bytes"], &general["evictions"]), (&after["bytes"], &after["evictions"]));
+}
+
+#[test]
+fn slab_classes_only_evict_their_own_size() {
+    // Chunks of 96, 192, 384 bytes and so on, room for four small items
+    let store = MemoryStore::new().with_memory_limit(4 * 96).with_slab_classes(2.0);
+    for key in &["a", "b", "c", "d"] {
+        cas(store.handle(set(key, "small", 0, 0)));
+    }
+    let large = "v".repeat(200);
+    assert_eq!(ResponseStatus::OutOfMemory, store.handle(set("e", &large, 0, 0)).status());
+    assert_eq!(4, store.len());
+
+    cas(store.handle(set("e", "small", 0, 0)));
+    assert_eq!(None, value(&store, "a"));
+    let items = stats(&store, "items");
+    assert_eq!("4", items["items:1:number"]);
+    assert_eq!("1", items["items:1:evicted"]);
+    assert!(!items.contains_key("items:3:number"));
+}
+
+#[test]
+fn flushed_items_are_not_evicted() {
+    // Room for five items in 768 byte chunks
+    let store = MemoryStore::new().with_memory_limit(3860).with_slab_classes(2.0);
+    for key in &["a", "b", "c"] {
+        cas(store.handle(set(key, "small", 0, 0)));
+    }
+    assert_eq!(Reply::Flushed, store.handle(Command::Flush { expiration: None, quiet: false }));
+    let large = "v".repeat(700);
+    for key in &["d", "e", "f", "g", "h"] {
+        cas(store.handle(set(key, &large, 0, 0)));
+    }
+    assert_eq!(ResponseStatus::OutOfMemory, store.handle(set("a", "small", 0, 0)).status());
+    cas(store.handle(set("i", &large, 0, 0)));
+    assert_eq!(None, value(&store, "d"));
+    assert_eq!(5, store.len());
+    assert_eq!("1", stats(&store, "")["evictions"]);
+}
+
+#[test]
+fn client_against_the_store() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    thread::spawn(move || serve(listener, Arc::new(MemoryStore::new())));
+    let mut client = Client::new(TcpStream::connect(address).unwrap());
+
+    let cas = client.set(b"Hello", b"World", 3, 0).unwrap();
+    let item = client.get(b"Hello").unwrap().unwrap();
+    assert_eq!((b"World".to_vec(), 3, cas), (item.value, item.flags, item.cas));
+    assert_eq!(Some(ResponseStatus::KeyExists),
+               client.add(b"Hello", b"", 0, 0).unwrap_err().status());
+    assert_eq!(10, client.incr(b"counter", 1, 10, 0).unwrap());
+    assert_eq!(11, client.incr(b"counter", 1, 10, 0).unwrap());
+    let items = client.get_multi(vec!["Hello", "counter", "missing"]).unwrap();
+    assert_eq!(2, items.len());
+    assert_eq!("2", client.stats(b"").unwrap()["curr_items"]);
+    client.flush(None).unwrap();
+    assert_eq!(None, client.get(b"Hello").unwrap());
+    client.quit().unwrap();
+}
@@ -0,0 +1,150 @@
+extern crate memcache_protocol;
+use memcache_protocol::*;
+
+use std::collections::HashMap;
+use std::net::TcpListener;
+use std::thread;
+
+#[cfg(feature = "serde_json")]
+const CONFIG: &'static str = r#"{
+    "name": "default",
+    "vBucketServerMap": {
+        "hashAlgorithm": "CRC",
+        "numReplicas": 1,
+        "serverList": ["10.0.0.1:11210", "10.0.0.2:11210"],
+        "vBucketMap": [[0, 1], [1, 0], [1, -1], [-1, -1]]
+    }
+}"#;
+
+// Answers WrongServer with config to everything, or keeps items if it owns
+// them, checking every request carries the vBucket of its key.
+fn start(owner: bool, config: String) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut buf = Vec::new();
+            let mut items = HashMap::new();
+            while read_packet(&mut stream, &mut buf).is_ok() {
+                let (_, request) = packet(&buf).unwrap();
+                let (opcode, opaque, vbucket) = match request.header {
+                    HeaderType::Request(ref h) => (h.opcode, h.opaque, h.vbucket_id),
+                    _ => panic!(),
+                };
+                let command = Command::from_packet(&request).unwrap();
+                assert_eq!(vbucket_id(command.key(), 4), vbucket);
+                let reply = match command {
+                    _ if !owner => Reply::Error(ResponseStatus::WrongServer, config.clone()),
+                    Command::Set { key, value, .. } => {
+                        items.insert(key, value);
+                        Reply::Stored { cas: 1 }
+                    }
+                    Command::Get { key, .. } => {
+                        match items.get(&key) {
+                            Some(value) => {
+                                Reply::Value {
+                                    flags: 0,
+                                    cas: 1,
+                                    key: Vec::new(),
+                                    value: value.clone(),
+                                }
+                            }
+                            None => Reply::Error(ResponseStatus::KeyNotFound, String::new()),
+                        }
+                    }
+                    command => panic!("{:?}", command),
+                };
+                reply.write_to(opcode, opaque, &mut stream).unwrap();
+            }
+        }
+    });
+    address
+}
+
+fn server_map(servers: &[&String], master: usize) -> VBucketMap {
+    let servers = servers.iter().map(|server| server.to_string()).collect();
+    VBucketMap::new(servers, vec![vec![Some(master)]; 4]).unwrap()
+}
+
+#[cfg(feature = "serde_json")]
+fn server_map_json(servers: &[&String], master: usize) -> String {
+    format!(r#"{{"serverList": {:?}, "vBucketMap": [[{}], [{}], [{}], [{}]]}}"#,
+            servers,
+            master,
+            master,
+            master,
+            master)
+}
+
+#[test]
+fn keys_are_hashed_with_crc32() {
+    // The crc32 of apple and beat are 10542 and 22009
+    assert_eq!(302, vbucket_id(b"apple", 1024));
+    assert_eq!(505, vbucket_id(b"beat", 1024));
+    assert_eq!(0, vbucket_id(b"apple", 1));
+}
+
+#[test]
+#[cfg(feature = "serde_json")]
+fn maps_from_json() {
+    let mut map = VBucketMap::from_json(CONFIG).unwrap();
+    assert_eq!(&["10.0.0.1:11210".to_string(), "10.0.0.2:11210".to_string()], map.servers());
+    assert_eq!(4, map.len());
+    assert_eq!(Some(0), map.master(0));
+    assert_eq!(&[Some(0)], map.replicas(1));
+    assert_eq!(None, map.master(3));
+    assert_eq!(vbucket_id(b"apple", 4), map.vbucket(b"apple"));
+
+    assert_eq!(Some(1), map.found_incorrect_master(0, 0));
+    assert_eq!(Some(1), map.found_incorrect_master(0, 0));
+    assert_eq!(Some(0), map.found_incorrect_master(0, 1));
+
+    let bare = r#"{"serverList": ["a:1"], "vBucketMap": [[0], [0]]}"#;
+    assert_eq!(2, VBucketMap::from_json(bare).unwrap().len());
+    for invalid in &[r#"{"serverList": ["a:1"], "vBucketMap": [[0], [0], [0]]}"#,
+                     r#"{"serverList": ["a:1"], "vBucketMap": [[1]]}"#,
+                     r#"{"hashAlgorithm": "MD5", "serverList": ["a:1"], "vBucketMap": [[0]]}"#,
+                     r#"{"serverList": ["a:1"]}"#] {
+        match VBucketMap::from_json(invalid) {
+            Err(VBucketMapError::Invalid(_)) => (),
+            other => panic!("{}: {:?}", invalid, other),
+        }
+    }
+    assert!(VBucketMap::from_json("{").is_err());
+}
+
+#[test]
+fn wrong_server_moves_to_the_next() {
+    let wrong = start(false, String::new());
+    let right = start(true, String::new());
+    let map = server_map(&[&wrong, &right], 0);
+    let mut client = VBucketClient::new(map);
+    client.set(b"apple", b"value", 0, 0).unwrap();
+    assert_eq!(b"value".to_vec(), client.get(b"apple").unwrap().unwrap().value);
+    let vbucket = client.map().vbucket(b"apple");
+    assert_eq!(Some(1), client.map().master(vbucket));
+}
+
+#[test]
+#[cfg(feature = "serde_json")]
+fn wrong_server_sends_a_new_map() {
+    let right = start(true, String::new());
+    let wrong = start(false, server_map_json(&[&right], 0));
+    let other = start(false, String::new());
+    let map = server_map(&[&wrong, &other], 0);
+    let mut client = VBucketClient::new(map);
+    client.set(b"apple", b"value", 0, 0).unwrap();
+    assert_eq!(&[right], client.map().servers());
+    assert_eq!(b"value".to_vec(), client.get(b"apple").unwrap().unwrap().value);
+}
+
+#[test]
+fn no_server_owns_the_vbucket() {
+    let first = start(false, String::new());
+    let second = start(false, String::new());
+    let map = server_map(&[&first, &second], 0);
+    let mut client = VBucketClient::new(map);
+    let err = client.get(b"apple").unwrap_err();
+    assert_eq!(Some(ResponseStatus::WrongServer), err.status());
+}